@@ -2,11 +2,11 @@ use std::{sync::Arc, time::Duration};
 
 use futures::SinkExt;
 use patternfly_yew::{use_toaster, Toast, ToastViewer, Type};
-use tickoattwo::packet::{Event, Packet};
+use tickoattwo::packet::{Encoding, Event, Handshake, Packet, PROTOCOL_VERSION};
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::HtmlInputElement;
-use ws_stream_wasm::{WsErr, WsMessage, WsMeta};
+use ws_stream_wasm::{WsErr, WsMeta};
 use yew::prelude::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -19,10 +19,18 @@ enum GameState {
 async fn websocket(username: String) -> Result<(), WsErr> {
     let (ws, mut io) = WsMeta::connect("ws://127.0.0.1:8080/api/ws", None).await?;
 
-    io.send(WsMessage::Text(
-        Packet::new(Event::Nickname(username)).encode_raw(),
-    ))
-    .await?;
+    let hello = Handshake {
+        version: PROTOCOL_VERSION,
+        features: Vec::new(),
+    };
+    io.send(Packet::new(Event::Hello(hello)).encode_ws_message(Encoding::Text))
+        .await?;
+
+    io.send(Packet::new(Event::Nickname(username)).encode_ws_message(Encoding::Text))
+        .await?;
+
+    io.send(Packet::new(Event::JoinQueue).encode_ws_message(Encoding::Text))
+        .await?;
 
     let ev = ws.close().await?;
     if !ev.was_clean {
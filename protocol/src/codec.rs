@@ -0,0 +1,370 @@
+// Takes events apart into their binary tag, name and payload and puts them back
+// together, driven only by the serde representation of Event. Payloads are
+// encoded with postcard, so numbers stay numbers, uuids stay 16 bytes and
+// nothing has to be valid UTF-8 apart from the strings in it.
+use serde::{
+    de::{self, DeserializeSeed, IntoDeserializer, SeqAccess, Visitor},
+    ser::{self, Impossible, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt;
+
+use crate::packet::{DecodeError, Event};
+
+// Identifies an event by its tag in binary or its name in text frames
+pub enum Variant<'a> {
+    Tag(u8),
+    Name(&'a str),
+}
+
+impl fmt::Display for Variant<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Tag(tag) => write!(f, "{}", tag),
+            Variant::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// Tag, name and payload of the event, the payload is empty for events without one
+pub fn encode(event: &Event) -> (u8, &'static str, Vec<u8>) {
+    event
+        .serialize(Envelope)
+        .expect("events split into tag, name and payload")
+}
+
+pub fn decode(variant: Variant, payload: &[u8]) -> Result<Event, DecodeError> {
+    let event = variant.to_string();
+    Event::deserialize(Joined {
+        variant: Some(variant),
+        payload: Some(payload),
+    })
+    .map_err(|err| match err {
+        Error::Tag => DecodeError::UnknownEvent(event),
+        _ => DecodeError::InvalidPayload(event),
+    })
+}
+
+// Boards are packed into two bits per field in formats that aren't human
+// readable, like the binary encoding and MessagePack
+pub mod board {
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::game::{Board, FieldState, Player};
+
+    const FIELDS: [FieldState; 4] = [
+        FieldState::None,
+        FieldState::OccupiedByOne(Player::Horizontal),
+        FieldState::OccupiedByOne(Player::Vertical),
+        FieldState::Both,
+    ];
+
+    pub fn serialize<S: Serializer>(board: &Board, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return board.serialize(serializer);
+        }
+
+        let packed = board
+            .iter()
+            .flatten()
+            .enumerate()
+            .fold(0, |packed, (i, field)| {
+                let bits = FIELDS.iter().position(|f| f == field).unwrap() as u32;
+                packed | bits << (2 * i)
+            });
+        serializer.serialize_u32(packed)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Board, D::Error> {
+        if deserializer.is_human_readable() {
+            return Board::deserialize(deserializer);
+        }
+
+        let packed = u32::deserialize(deserializer)?;
+        if packed >> 18 != 0 {
+            return Err(de::Error::custom("board has more than 9 fields"));
+        }
+
+        let mut board = [[FieldState::None; 3]; 3];
+        for i in 0..9 {
+            board[i / 3][i % 3] = FIELDS[(packed >> (2 * i)) as usize & 0b11];
+        }
+        Ok(board)
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    // Tag or name is not one of an event
+    Tag,
+    // Payload does not match the event
+    Payload,
+    // Event is not serialized as an adjacently tagged enum
+    Unsupported,
+    Custom(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Tag => write!(f, "unknown event"),
+            Error::Payload => write!(f, "invalid payload"),
+            Error::Unsupported => write!(f, "unsupported event representation"),
+            Error::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Custom(message.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Custom(message.to_string())
+    }
+}
+
+// Serializes an event as a struct of the tag, which is a unit variant, and the payload
+struct Envelope;
+
+struct Fields(Option<(u8, &'static str, Vec<u8>)>);
+
+impl SerializeStruct for Fields {
+    type Ok = (u8, &'static str, Vec<u8>);
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        match &mut self.0 {
+            None => self.0 = Some(value.serialize(Envelope)?),
+            Some((_, _, payload)) => {
+                *payload = postcard::to_allocvec(value).map_err(|_| Error::Payload)?
+            }
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.0.ok_or(Error::Unsupported)
+    }
+}
+
+impl Serializer for Envelope {
+    type Ok = (u8, &'static str, Vec<u8>);
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = Impossible<Self::Ok, Error>;
+    type SerializeStruct = Fields;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        let tag = u8::try_from(index).map_err(|_| Error::Tag)?;
+        Ok((tag, variant, Vec::new()))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Fields, Error> {
+        Ok(Fields(None))
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::Unsupported)
+    }
+}
+
+// Hands the tag and then the payload to the deserializer of Event
+struct Joined<'de> {
+    variant: Option<Variant<'de>>,
+    payload: Option<&'de [u8]>,
+}
+
+impl<'de> Deserializer<'de> for Joined<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> SeqAccess<'de> for Joined<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if let Some(variant) = self.variant.take() {
+            let tag = match variant {
+                Variant::Tag(tag) => seed.deserialize(u64::from(tag).into_deserializer()),
+                Variant::Name(name) => seed.deserialize(name.into_deserializer()),
+            };
+            return tag.map(Some).map_err(|_: Error| Error::Tag);
+        }
+
+        match self.payload.take() {
+            // Events without payload are unit variants
+            Some([]) => seed
+                .deserialize(().into_deserializer())
+                .map(Some)
+                .map_err(|_: Error| Error::Payload),
+            Some(payload) => {
+                let mut deserializer = postcard::Deserializer::from_bytes(payload);
+                let value = seed
+                    .deserialize(&mut deserializer)
+                    .map_err(|_| Error::Payload)?;
+                match deserializer.finalize() {
+                    Ok([]) => Ok(Some(value)),
+                    _ => Err(Error::Payload),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+}
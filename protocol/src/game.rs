@@ -0,0 +1,799 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Player {
+    Horizontal,
+    Vertical,
+}
+
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::Horizontal => Player::Vertical,
+            Player::Vertical => Player::Horizontal,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldState {
+    None,
+    OccupiedByOne(Player),
+    Both,
+}
+
+pub type Board = [[FieldState; 3]; 3];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Line {
+    Row(u8),
+    Column(u8),
+    // 0 runs from the top left, 1 from the top right corner
+    Diagonal(u8),
+}
+
+const LINES: [(Line, [(usize, usize); 3]); 8] = [
+    (Line::Row(0), [(0, 0), (0, 1), (0, 2)]),
+    (Line::Row(1), [(1, 0), (1, 1), (1, 2)]),
+    (Line::Row(2), [(2, 0), (2, 1), (2, 2)]),
+    (Line::Column(0), [(0, 0), (1, 0), (2, 0)]),
+    (Line::Column(1), [(0, 1), (1, 1), (2, 1)]),
+    (Line::Column(2), [(0, 2), (1, 2), (2, 2)]),
+    (Line::Diagonal(0), [(0, 0), (1, 1), (2, 2)]),
+    (Line::Diagonal(1), [(0, 2), (1, 1), (2, 0)]),
+];
+
+// Player whose move completed the winning line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Win {
+    pub player: Player,
+    pub line: Line,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameOutcome {
+    InProgress,
+    Win(Win),
+    // Player to move has no field left to occupy
+    Draw,
+    // Player to move could only occupy the field placed in last round
+    Stalemate(Player),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveError {
+    OutOfBounds,
+    RepeatedLastMove,
+    AlreadyOwnField,
+    FieldFull,
+    GameOver,
+    NotYourTurn,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            MoveError::OutOfBounds => "Invalid coordinates",
+            MoveError::RepeatedLastMove => "Invalid move: placed in last round",
+            MoveError::AlreadyOwnField => "Invalid move: already placed",
+            MoveError::FieldFull => "Invalid move: field is full",
+            MoveError::GameOver => "Invalid move: game is over",
+            MoveError::NotYourTurn => "Invalid move: not your turn",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for MoveError {}
+
+// Move together with the state it replaced
+#[derive(Debug, Copy, Clone)]
+struct HistoryEntry {
+    coords: (u8, u8),
+    field: FieldState,
+    previous_move: Option<(u8, u8)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    board: Board,
+    current_player: Player,
+    previous_move: Option<(u8, u8)>,
+    winner: Option<Win>,
+    history: Vec<HistoryEntry>,
+    // Undone moves, the next one to redo last
+    redo_moves: Vec<(u8, u8)>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game {
+            board: [[FieldState::None; 3]; 3],
+            current_player: Player::Horizontal,
+            previous_move: None,
+            winner: None,
+            history: Vec::new(),
+            redo_moves: Vec::new(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn current_player(&self) -> Player {
+        self.current_player
+    }
+
+    pub fn previous_move(&self) -> Option<(u8, u8)> {
+        self.previous_move
+    }
+
+    // Whether the field can be taken by the player to move, regardless of the last round
+    fn can_occupy(&self, coords: (u8, u8)) -> bool {
+        match self.board[coords.0 as usize][coords.1 as usize] {
+            FieldState::None => true,
+            FieldState::OccupiedByOne(player) => player != self.current_player,
+            FieldState::Both => false,
+        }
+    }
+
+    pub fn is_legal(&self, coords: (u8, u8)) -> bool {
+        self.winner.is_none()
+            && coords.0 < 3
+            && coords.1 < 3
+            && self.previous_move != Some(coords)
+            && self.can_occupy(coords)
+    }
+
+    pub fn legal_moves(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (0..3)
+            .flat_map(|row| (0..3).map(move |col| (row, col)))
+            .filter(|&coords| self.is_legal(coords))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.outcome() != GameOutcome::InProgress
+    }
+
+    // Make a move on behalf of the given player, rejecting it if not their turn
+    pub fn make_move_as(&mut self, player: Player, coords: (u8, u8)) -> Result<(), MoveError> {
+        if self.is_finished() {
+            return Err(MoveError::GameOver);
+        }
+
+        if player != self.current_player {
+            return Err(MoveError::NotYourTurn);
+        }
+
+        self.make_move(coords)
+    }
+
+    pub fn make_move(&mut self, coords: (u8, u8)) -> Result<(), MoveError> {
+        self.play(coords)?;
+
+        // New move discards the undone ones
+        self.redo_moves.clear();
+
+        Ok(())
+    }
+
+    fn play(&mut self, coords: (u8, u8)) -> Result<(), MoveError> {
+        // No moves after the game has ended
+        if self.is_finished() {
+            return Err(MoveError::GameOver);
+        }
+
+        // Out of bound check
+        if coords.0 >= 3 || coords.1 >= 3 {
+            return Err(MoveError::OutOfBounds);
+        }
+
+        // Check if field was occupied the in last round
+        if self.previous_move.is_some() && coords == self.previous_move.unwrap() {
+            return Err(MoveError::RepeatedLastMove);
+        }
+
+        let field = &mut self.board[coords.0 as usize][coords.1 as usize];
+
+        // Check if field can be occupied
+        let occupied = match field {
+            FieldState::None => FieldState::OccupiedByOne(self.current_player),
+            FieldState::OccupiedByOne(player) => {
+                if *player == self.current_player {
+                    return Err(MoveError::AlreadyOwnField);
+                }
+                FieldState::Both
+            }
+            FieldState::Both => return Err(MoveError::FieldFull),
+        };
+
+        // Move is valid, commit it
+        self.history.push(HistoryEntry {
+            coords,
+            field: *field,
+            previous_move: self.previous_move,
+        });
+        *field = occupied;
+        self.previous_move = Some(coords);
+
+        // Completed line ends the game
+        self.winner = self.find_line().map(|line| Win {
+            player: self.current_player,
+            line,
+        });
+
+        // Set next player
+        self.current_player = self.current_player.opponent();
+
+        Ok(())
+    }
+
+    // Moves made so far, oldest first
+    pub fn history(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.history.iter().map(|entry| entry.coords)
+    }
+
+    // Take back the last move, returning its coordinates
+    pub fn undo(&mut self) -> Option<(u8, u8)> {
+        let entry = self.history.pop()?;
+
+        self.board[entry.coords.0 as usize][entry.coords.1 as usize] = entry.field;
+        self.previous_move = entry.previous_move;
+        self.current_player = self.current_player.opponent();
+        // Game ends with the first line, so no earlier position has a winner
+        self.winner = None;
+
+        self.redo_moves.push(entry.coords);
+
+        Some(entry.coords)
+    }
+
+    // Replay the last undone move, returning its coordinates
+    pub fn redo(&mut self) -> Option<(u8, u8)> {
+        let coords = self.redo_moves.pop()?;
+
+        if self.play(coords).is_err() {
+            self.redo_moves.push(coords);
+            return None;
+        }
+
+        Some(coords)
+    }
+
+    pub fn check_win(&self) -> Option<Win> {
+        self.winner
+    }
+
+    // Find a line of fields occupied by both players
+    fn find_line(&self) -> Option<Line> {
+        LINES.iter().find_map(|(line, fields)| {
+            fields
+                .iter()
+                .all(|&(row, col)| self.board[row][col] == FieldState::Both)
+                .then_some(*line)
+        })
+    }
+
+    pub fn outcome(&self) -> GameOutcome {
+        if let Some(win) = self.check_win() {
+            return GameOutcome::Win(win);
+        }
+
+        if self.legal_moves().next().is_some() {
+            return GameOutcome::InProgress;
+        }
+
+        let blocked_by_previous_move = self
+            .previous_move
+            .is_some_and(|coords| self.can_occupy(coords));
+
+        if blocked_by_previous_move {
+            GameOutcome::Stalemate(self.current_player)
+        } else {
+            GameOutcome::Draw
+        }
+    }
+}
+
+// Moves in which vertical completes the top row
+#[cfg(test)]
+pub(crate) const TOP_ROW_WIN: [(u8, u8); 6] = [(0, 0), (0, 1), (0, 2), (0, 0), (0, 1), (0, 2)];
+
+// Game after the moves, which have to be legal
+#[cfg(test)]
+pub(crate) fn play(moves: &[(u8, u8)]) -> Game {
+    let mut game = Game::new();
+    for coords in moves {
+        assert_eq!(game.make_move(*coords), Ok(()));
+    }
+    game
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::IteratorRandom;
+
+    #[test]
+    fn test_check_win() {
+        // Test no winner
+        assert_eq!(Game::new().check_win(), None);
+        assert_eq!(play(&TOP_ROW_WIN[..5]).check_win(), None);
+    }
+
+    // Test horizontal player completing a line
+    #[test]
+    fn test_check_win_horizontal() {
+        let game = play(&[(0, 0), (1, 0), (1, 1), (1, 2), (1, 0), (1, 1), (1, 2)]);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Horizontal,
+                line: Line::Row(1),
+            })
+        );
+
+        let game = play(&[(0, 0), (0, 2), (1, 2), (2, 2), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Horizontal,
+                line: Line::Column(2),
+            })
+        );
+    }
+
+    // Test vertical player completing a line
+    #[test]
+    fn test_check_win_vertical() {
+        let game = play(&TOP_ROW_WIN);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Vertical,
+                line: Line::Row(0),
+            })
+        );
+
+        let game = play(&[(0, 1), (1, 1), (2, 1), (0, 1), (1, 1), (2, 1)]);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Vertical,
+                line: Line::Column(1),
+            })
+        );
+    }
+
+    // Test diagonal lines
+    #[test]
+    fn test_check_win_diagonal() {
+        let game = play(&[(0, 0), (1, 1), (0, 1), (2, 2), (1, 1), (0, 0), (2, 2)]);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Horizontal,
+                line: Line::Diagonal(0),
+            })
+        );
+
+        let game = play(&[(0, 2), (1, 1), (2, 0), (0, 2), (1, 1), (2, 0)]);
+        assert_eq!(
+            game.check_win(),
+            Some(Win {
+                player: Player::Vertical,
+                line: Line::Diagonal(1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_in_progress() {
+        let mut game = Game::new();
+        assert_eq!(game.outcome(), GameOutcome::InProgress);
+
+        assert_eq!(game.make_move((1, 1)), Ok(()));
+        assert_eq!(game.make_move((0, 0)), Ok(()));
+        assert_eq!(game.outcome(), GameOutcome::InProgress);
+    }
+
+    #[test]
+    fn test_outcome_win() {
+        let game = play(&[(2, 0), (2, 1), (2, 2), (2, 0), (2, 1), (2, 2)]);
+        assert_eq!(
+            game.outcome(),
+            GameOutcome::Win(Win {
+                player: Player::Vertical,
+                line: Line::Row(2),
+            })
+        );
+    }
+
+    // Test no field left for the player to move
+    #[test]
+    fn test_outcome_draw() {
+        let mut game = Game::new();
+        let h = FieldState::OccupiedByOne(Player::Horizontal);
+
+        game.board = [
+            [FieldState::Both, h, FieldState::Both],
+            [h, h, h],
+            [FieldState::Both, h, FieldState::Both],
+        ];
+        game.current_player = Player::Horizontal;
+        game.previous_move = Some((0, 0));
+        assert_eq!(game.outcome(), GameOutcome::Draw);
+
+        // The opponent could still move here
+        game.current_player = Player::Vertical;
+        assert_eq!(game.outcome(), GameOutcome::InProgress);
+    }
+
+    // Test only field left for the player to move was placed in last round
+    #[test]
+    fn test_outcome_stalemate() {
+        let mut game = Game::new();
+        let h = FieldState::OccupiedByOne(Player::Horizontal);
+        let v = FieldState::OccupiedByOne(Player::Vertical);
+
+        game.board = [
+            [FieldState::Both, h, FieldState::Both],
+            [h, v, h],
+            [FieldState::Both, h, FieldState::Both],
+        ];
+        game.current_player = Player::Horizontal;
+        game.previous_move = Some((1, 1));
+        assert_eq!(game.outcome(), GameOutcome::Stalemate(Player::Horizontal));
+
+        game.previous_move = Some((0, 0));
+        assert_eq!(game.outcome(), GameOutcome::InProgress);
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let game = Game::new();
+        assert_eq!(game.legal_moves().count(), 9);
+
+        let game = play(&[(0, 0), (1, 1)]);
+        assert!(!game.is_legal((0, 0)));
+        assert!(!game.is_legal((1, 1)));
+        assert!(!game.is_legal((3, 0)));
+        assert!(game.is_legal((0, 1)));
+        assert_eq!(game.legal_moves().count(), 7);
+
+        // Own and full fields are skipped
+        let game = play(&[(0, 0), (1, 1), (0, 1), (0, 0)]);
+        assert_eq!(
+            game.legal_moves().collect::<Vec<_>>(),
+            vec![(0, 2), (1, 0), (1, 1), (1, 2), (2, 0), (2, 1), (2, 2)]
+        );
+    }
+
+    // Test is_legal agrees with make_move on every field
+    #[test]
+    fn test_is_legal_matches_make_move() {
+        fn check(game: &Game, depth: u8) {
+            for row in 0..4 {
+                for col in 0..4 {
+                    let mut next = game.clone();
+                    let result = next.make_move((row, col));
+                    assert_eq!(game.is_legal((row, col)), result.is_ok());
+
+                    if result.is_ok() && depth > 0 {
+                        check(&next, depth - 1);
+                    }
+                }
+            }
+        }
+
+        check(&Game::new(), 4);
+    }
+
+    // Test rejected moves leave the game untouched in every position up to a depth
+    #[test]
+    fn test_make_move_failure_keeps_state() {
+        fn check(game: &Game, depth: u8) {
+            for row in 0..4 {
+                for col in 0..4 {
+                    let mut next = game.clone();
+                    match next.make_move((row, col)) {
+                        Ok(()) if depth > 0 => check(&next, depth - 1),
+                        Ok(()) => {}
+                        Err(_) => {
+                            assert_eq!(next.board, game.board);
+                            assert_eq!(next.current_player, game.current_player);
+                            assert_eq!(next.previous_move, game.previous_move);
+                            assert_eq!(next.winner, game.winner);
+                        }
+                    }
+                }
+            }
+        }
+
+        check(&Game::new(), 5);
+    }
+
+    // Test no moves are accepted after a win
+    #[test]
+    fn test_make_move_game_over() {
+        let mut game = play(&TOP_ROW_WIN);
+
+        assert!(game.is_finished());
+        assert_eq!(game.make_move((2, 2)), Err(MoveError::GameOver));
+        assert_eq!(
+            game.make_move_as(Player::Horizontal, (2, 2)),
+            Err(MoveError::GameOver)
+        );
+        assert!(!game.is_legal((2, 2)));
+        assert_eq!(game.legal_moves().count(), 0);
+    }
+
+    // Test no moves are accepted after a stalemate
+    #[test]
+    fn test_make_move_stalemate() {
+        let mut game = Game::new();
+        let h = FieldState::OccupiedByOne(Player::Horizontal);
+        let v = FieldState::OccupiedByOne(Player::Vertical);
+
+        game.board = [
+            [FieldState::Both, h, FieldState::Both],
+            [h, v, h],
+            [FieldState::Both, h, FieldState::Both],
+        ];
+        game.previous_move = Some((1, 1));
+
+        assert!(game.is_finished());
+        assert_eq!(game.make_move((1, 1)), Err(MoveError::GameOver));
+    }
+
+    // Test moves out of turn
+    #[test]
+    fn test_make_move_as() {
+        let mut game = Game::new();
+
+        assert_eq!(
+            game.make_move_as(Player::Vertical, (0, 0)),
+            Err(MoveError::NotYourTurn)
+        );
+        assert_eq!(game.make_move_as(Player::Horizontal, (0, 0)), Ok(()));
+        assert_eq!(
+            game.make_move_as(Player::Horizontal, (1, 1)),
+            Err(MoveError::NotYourTurn)
+        );
+        assert_eq!(game.make_move_as(Player::Vertical, (1, 1)), Ok(()));
+        assert_eq!(game.current_player(), Player::Horizontal);
+    }
+
+    fn assert_same_position(game: &Game, other: &Game) {
+        assert_eq!(game.board, other.board);
+        assert_eq!(game.current_player, other.current_player);
+        assert_eq!(game.previous_move, other.previous_move);
+        assert_eq!(game.winner, other.winner);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = play(&[(0, 0), (1, 1)]);
+        let after_first = play(&[(0, 0)]);
+
+        assert_eq!(game.undo(), Some((1, 1)));
+        assert_same_position(&game, &after_first);
+        assert_eq!(game.history().collect::<Vec<_>>(), vec![(0, 0)]);
+
+        assert_eq!(game.redo(), Some((1, 1)));
+        assert_same_position(&game, &play(&[(0, 0), (1, 1)]));
+        assert_eq!(game.redo(), None);
+
+        // New move discards the undone ones
+        assert_eq!(game.undo(), Some((1, 1)));
+        assert_eq!(game.make_move((2, 2)), Ok(()));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.history().collect::<Vec<_>>(), vec![(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn test_undo_win() {
+        let mut game = play(&TOP_ROW_WIN);
+
+        assert_eq!(game.undo(), Some((0, 2)));
+        assert_same_position(&game, &play(&TOP_ROW_WIN[..5]));
+        assert_eq!(game.outcome(), GameOutcome::InProgress);
+
+        assert_eq!(game.redo(), Some((0, 2)));
+        assert_eq!(
+            game.check_win().map(|win| win.player),
+            Some(Player::Vertical)
+        );
+    }
+
+    // Test undoing random games move by move back to the start
+    #[test]
+    fn test_undo_random_games() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let mut game = Game::new();
+            let mut positions = vec![game.clone()];
+
+            while let Some(coords) = game.legal_moves().choose(&mut rng) {
+                assert_eq!(game.make_move(coords), Ok(()));
+                positions.push(game.clone());
+            }
+
+            let moves = game.history().collect::<Vec<_>>();
+            positions.pop();
+
+            for coords in moves.iter().rev() {
+                assert_eq!(game.undo(), Some(*coords));
+                assert_same_position(&game, &positions.pop().unwrap());
+            }
+            assert_eq!(game.undo(), None);
+            assert_same_position(&game, &Game::new());
+
+            for coords in moves.iter() {
+                assert_eq!(game.redo(), Some(*coords));
+            }
+            assert_eq!(game.history().collect::<Vec<_>>(), moves);
+        }
+    }
+
+    // Test invalid coordinates
+    #[test]
+    fn test_make_move_bounds() {
+        let mut game = Game::new();
+
+        assert_eq!(game.make_move((3, 0)), Err(MoveError::OutOfBounds));
+    }
+
+    // Test invalid move: placed in last round
+    #[test]
+    fn test_make_move_last_round() {
+        let mut game = Game::new();
+
+        game.previous_move = Some((0u8, 0u8));
+        assert_eq!(game.make_move((0, 0)), Err(MoveError::RepeatedLastMove));
+    }
+
+    // Test invalid move: already placed
+    #[test]
+    fn test_make_move_already_placed() {
+        let mut game = Game::new();
+
+        // Test valid move
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(
+            game.board[0][1],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 1)), Ok(()));
+        assert_eq!(
+            game.board[1][1],
+            FieldState::OccupiedByOne(Player::Vertical)
+        );
+
+        // Test invalid move: already placed
+        assert_eq!(game.make_move((0, 1)), Err(MoveError::AlreadyOwnField));
+
+        // Rejected move does not count as placed in last round
+        assert_eq!(game.previous_move, Some((1, 1)));
+    }
+
+    // Test valid move
+    #[test]
+    fn test_make_move_valid() {
+        let mut game = Game::new();
+
+        // Test valid move
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(
+            game.board[0][1],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 1)), Ok(()));
+        assert_eq!(
+            game.board[1][1],
+            FieldState::OccupiedByOne(Player::Vertical)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 2)), Ok(()));
+        assert_eq!(
+            game.board[1][2],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((2, 2)), Ok(()));
+        assert_eq!(
+            game.board[2][2],
+            FieldState::OccupiedByOne(Player::Vertical)
+        );
+    }
+
+    // Test valid move
+    #[test]
+    fn test_make_move_valid_both() {
+        let mut game = Game::new();
+
+        // Test valid move
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(
+            game.board[0][1],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 1)), Ok(()));
+        assert_eq!(
+            game.board[1][1],
+            FieldState::OccupiedByOne(Player::Vertical)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 2)), Ok(()));
+        assert_eq!(
+            game.board[1][2],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move both
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(game.board[0][1], FieldState::Both);
+    }
+
+    // Test invalid move
+    #[test]
+    fn test_make_move_invalid_both() {
+        let mut game = Game::new();
+
+        // Test valid move
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(
+            game.board[0][1],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 1)), Ok(()));
+        assert_eq!(
+            game.board[1][1],
+            FieldState::OccupiedByOne(Player::Vertical)
+        );
+
+        // Test valid move
+        assert_eq!(game.make_move((1, 2)), Ok(()));
+        assert_eq!(
+            game.board[1][2],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test valid move both
+        assert_eq!(game.make_move((0, 1)), Ok(()));
+        assert_eq!(game.board[0][1], FieldState::Both);
+
+        // Test valid move
+        assert_eq!(game.make_move((2, 1)), Ok(()));
+        assert_eq!(
+            game.board[2][1],
+            FieldState::OccupiedByOne(Player::Horizontal)
+        );
+
+        // Test invalid move both
+        assert_eq!(game.make_move((0, 1)), Err(MoveError::FieldFull));
+    }
+}
@@ -1 +1,5 @@
-pub mod packet;
\ No newline at end of file
+mod codec;
+pub mod game;
+pub mod packet;
+pub mod solver;
+pub mod transport;
@@ -1,12 +1,267 @@
 use base64;
-use tungstenite::Message;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::codec::{self, Variant};
+use crate::game::{Board, MoveError, Player};
+
+// Version of the protocol implemented by this crate
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Protocol version and optional features, exchanged before any other packet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version: u32,
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Perfect,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NicknameError {
+    TooShort,
+    TooLong,
+    // Only letters, digits, spaces and - _ . are allowed
+    InvalidCharacter,
+    // Another online user has the nickname
+    Taken,
+    // Nickname contains a blocked word
+    Blocked,
+}
+
+impl fmt::Display for NicknameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NicknameError::TooShort => write!(f, "Nickname is too short"),
+            NicknameError::TooLong => write!(f, "Nickname is too long"),
+            NicknameError::InvalidCharacter => write!(f, "Nickname contains invalid characters"),
+            NicknameError::Taken => write!(f, "Nickname is already taken"),
+            NicknameError::Blocked => write!(f, "Nickname is not allowed"),
+        }
+    }
+}
+
+impl Error for NicknameError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomError {
+    // No room with the code exists
+    NotFound,
+    // Room already has two members
+    Full,
+}
+
+impl fmt::Display for RoomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoomError::NotFound => write!(f, "Room not found"),
+            RoomError::Full => write!(f, "Room is full"),
+        }
+    }
+}
+
+impl Error for RoomError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerError {
+    // Connection is not known to the server (anymore)
+    UnknownUser,
+    // Game of the user does not exist (anymore)
+    UnknownGame,
+    // Sender is watching a game and can't play in it
+    Spectating,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::UnknownUser => write!(f, "Unknown user"),
+            ServerError::UnknownGame => write!(f, "Unknown game"),
+            ServerError::Spectating => write!(f, "Spectators can't play"),
+        }
+    }
+}
+
+impl Error for ServerError {}
+
+// Running game as shown in the lobby
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbyGame {
+    pub id: Uuid,
+    // Nicknames of the horizontal and the vertical player
+    pub horizontal: String,
+    pub vertical: String,
+    pub moves: u32,
+    pub spectators: u32,
+}
+
+// Overview of what is going on on the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lobby {
+    pub games: Vec<LobbyGame>,
+    // Users waiting in the queue
+    pub queued: u32,
+    // Connected users
+    pub online: u32,
+}
+
+// In JSON, events are encoded as {"event": "move", "payload": [2, 1]}.
+// The index of a variant is its tag in the binary encoding, only ever append
+// variants to keep the tags of existing events stable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "payload", rename_all = "snake_case")]
 pub enum Event {
+    // First packet of the client, with what it supports
+    Hello(Handshake),
+    // Answer to the hello, with the version and features used from now on
+    Welcome(Handshake),
+    // Token to resume the session after the connection dropped
+    Session(Uuid),
+    // Take over the session with the token on a new connection
+    Resume(Uuid),
+    // Session is unknown or expired
+    ResumeFailed,
     Nickname(String),
+    // Nickname was accepted, as stored by the server
+    NicknameAccepted(String),
+    NicknameRejected(NicknameError),
+    // Wait for an opponent, also after a finished game
+    JoinQueue,
+    LeaveQueue,
+    // Play against a server side bot instead of waiting for an opponent
+    PlayBot(Difficulty),
+    // Nobody joined in time, with the difficulty of the bot game on offer
+    NoOpponent(Option<Difficulty>),
+    // Open a private room to play against a friend
+    CreateRoom,
+    // Room was opened, with the code to share
+    RoomCreated(String),
+    // Enter the private room with the code, the game starts once two users are in
+    JoinRoom(String),
+    LeaveRoom,
+    // Room could not be joined, with the reason
+    RoomError(RoomError),
+    // Watch the game with the id without taking part
+    Spectate(Uuid),
+    StopSpectating,
+    // Game to spectate does not exist (anymore)
+    GameNotFound,
+    // Number of users watching the game
+    SpectatorCount(u32),
+    // Receive the lobby now and whenever it changes
+    SubscribeLobby,
+    UnsubscribeLobby,
+    Lobby(Lobby),
+    // Game has started, with the player assigned to the receiver
+    Start(Player),
+    // Place a mark on the field at (row, column)
+    Move(u8, u8),
+    // Board after a move and the player to move next
+    State(#[serde(with = "crate::codec::board")] Board, Player),
+    // Move was rejected, with the reason
+    InvalidMove(MoveError),
+    Winner(Player),
+    // Opponent left the running game, the receiver wins and is queued again
+    OpponentLeft,
+    // Game ended without a winner
+    Draw,
+    // Event could not be handled by the server
+    Error(ServerError),
+    // Opponent lost the connection, with the seconds they have to come back
+    OpponentDisconnected(u32),
+    // Opponent resumed the game after losing the connection
+    OpponentReconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    InvalidMessage,
+    InvalidParts,
+    InvalidEncoding,
+    UnknownEvent(String),
+    // Payload does not match the event, with the event name or binary tag
+    InvalidPayload(String),
+    // Structured packet could not be deserialized, with the reason
+    InvalidStructure(String),
 }
 
-// Simple text based packet coder
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidMessage => write!(f, "invalid message"),
+            DecodeError::InvalidParts => write!(f, "invalid packet parts"),
+            DecodeError::InvalidEncoding => write!(f, "failed to decode payload"),
+            DecodeError::UnknownEvent(event) => write!(f, "unknown event type: {}", event),
+            DecodeError::InvalidPayload(event) => write!(f, "invalid {} payload", event),
+            DecodeError::InvalidStructure(reason) => write!(f, "invalid packet: {}", reason),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+// Features to negotiate in the handshake for encodings other than text
+pub const BINARY_FEATURE: &str = "binary";
+pub const JSON_FEATURE: &str = "json";
+pub const MSGPACK_FEATURE: &str = "msgpack";
+
+// How packets are framed on a connection, structured encodings depend on features
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Encoding {
+    // event:base64(payload) in text frames
+    Text,
+    // Event tag byte and payload in binary frames
+    Binary,
+    // Serialized event in text frames
+    #[cfg(feature = "json")]
+    Json,
+    // Serialized event in binary frames
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl Encoding {
+    // Encoding agreed on in the handshake, structured encodings are preferred
+    pub fn negotiated(handshake: &Handshake) -> Self {
+        let agreed = |feature| handshake.features.iter().any(|f| f == feature);
+
+        #[cfg(feature = "msgpack")]
+        if agreed(MSGPACK_FEATURE) {
+            return Encoding::MessagePack;
+        }
+        #[cfg(feature = "json")]
+        if agreed(JSON_FEATURE) {
+            return Encoding::Json;
+        }
+
+        if agreed(BINARY_FEATURE) {
+            Encoding::Binary
+        } else {
+            Encoding::Text
+        }
+    }
+}
+
+// Websocket frame independent of the transport, see crate::transport for adapters
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+// Packet coder for text, binary and structured frames
 pub struct Packet {
     pub event: Event,
 }
@@ -16,55 +271,381 @@ impl Packet {
         Self { event }
     }
 
-    pub fn decode_message(message: &Message) -> Result<Self, &str> {
-        let raw_message = match message.to_text() {
-            Ok(msg) => msg,
-            _ => return Err("invalid message"),
-        };
-        Self::decode_raw(raw_message)
+    pub fn decode_frame(frame: &Frame, encoding: Encoding) -> Result<Self, DecodeError> {
+        match frame {
+            Frame::Text(raw) => Self::decode_text_frame(raw, encoding),
+            Frame::Binary(bytes) => Self::decode_binary_frame(bytes, encoding),
+        }
     }
 
-    pub fn decode_raw(raw: &str) -> Result<Self, &str> {
-        let (raw_event, payload) = Self::decode_data(raw)?;
+    // Text and binary frames are both understood unless a structured encoding is used
+    pub fn decode_text_frame(raw: &str, encoding: Encoding) -> Result<Self, DecodeError> {
+        match encoding {
+            Encoding::Text | Encoding::Binary => Self::decode_raw(raw),
+            #[cfg(feature = "json")]
+            Encoding::Json => Self::decode_json(raw),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Err(DecodeError::InvalidMessage),
+        }
+    }
 
-        // Define event decoding here
-        let event = match raw_event.as_str() {
-            "nickname" => Event::Nickname(payload),
-            _ => return Err("unknown event type"),
-        };
+    pub fn decode_binary_frame(bytes: &[u8], encoding: Encoding) -> Result<Self, DecodeError> {
+        match encoding {
+            Encoding::Text | Encoding::Binary => Self::decode_binary(bytes),
+            #[cfg(feature = "json")]
+            Encoding::Json => Err(DecodeError::InvalidMessage),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Self::decode_msgpack(bytes),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    pub fn decode_json(raw: &str) -> Result<Self, DecodeError> {
+        serde_json::from_str(raw)
+            .map(Self::new)
+            .map_err(|err| DecodeError::InvalidStructure(err.to_string()))
+    }
+
+    #[cfg(feature = "msgpack")]
+    pub fn decode_msgpack(bytes: &[u8]) -> Result<Self, DecodeError> {
+        rmp_serde::from_slice(bytes)
+            .map(Self::new)
+            .map_err(|err| DecodeError::InvalidStructure(err.to_string()))
+    }
 
-        Ok(Self { event })
+    pub fn decode_raw(raw: &str) -> Result<Self, DecodeError> {
+        let (raw_event, payload) = Self::decode_data(raw)?;
+        codec::decode(Variant::Name(&raw_event), &payload).map(Self::new)
     }
 
-    fn decode_data(raw: &str) -> Result<(String, String), &str> {
+    pub fn decode_binary(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (tag, payload) = bytes.split_first().ok_or(DecodeError::InvalidParts)?;
+        codec::decode(Variant::Tag(*tag), payload).map(Self::new)
+    }
+
+    fn decode_data(raw: &str) -> Result<(String, Vec<u8>), DecodeError> {
         let parts = raw.split(':').collect::<Vec<&str>>();
 
         if parts.len() != 2 {
-            return Err("invalid packet parts");
+            return Err(DecodeError::InvalidParts);
         }
 
         let event = String::from(parts[0]);
-        if let Ok(bytes) = base64::decode(parts[1]) {
-            if let Ok(payload) = String::from_utf8(bytes) {
-                return Ok((event, payload));
-            }
+        match base64::decode(parts[1]) {
+            Ok(payload) => Ok((event, payload)),
+            Err(_) => Err(DecodeError::InvalidEncoding),
         }
+    }
 
-        Err("failed to decode payload")
+    pub fn encode_frame(&self, encoding: Encoding) -> Frame {
+        match encoding {
+            Encoding::Text => Frame::Text(self.encode_raw()),
+            Encoding::Binary => Frame::Binary(self.encode_binary()),
+            #[cfg(feature = "json")]
+            Encoding::Json => Frame::Text(self.encode_json()),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Frame::Binary(self.encode_msgpack()),
+        }
     }
 
-    pub fn encode_message(&self) -> Message {
-        Message::Text(self.encode_raw())
+    #[cfg(feature = "json")]
+    pub fn encode_json(&self) -> String {
+        serde_json::to_string(&self.event).expect("events serialize to json")
+    }
+
+    // Structs are encoded as maps to stay readable by other msgpack clients
+    #[cfg(feature = "msgpack")]
+    pub fn encode_msgpack(&self) -> Vec<u8> {
+        rmp_serde::to_vec_named(&self.event).expect("events serialize to msgpack")
     }
 
     pub fn encode_raw(&self) -> String {
-        // Define event encoding here
-        match &self.event {
-            Event::Nickname(nickname) => Self::encode_data("nickname", &nickname),
-        }
+        let (_, event, payload) = codec::encode(&self.event);
+        Self::encode_data(event, &payload)
     }
 
-    fn encode_data(event: &str, payload: &str) -> String {
+    // Tag byte of the event followed by the payload
+    pub fn encode_binary(&self) -> Vec<u8> {
+        let (tag, _, payload) = codec::encode(&self.event);
+
+        let mut bytes = Vec::with_capacity(1 + payload.len());
+        bytes.push(tag);
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    fn encode_data(event: &str, payload: &[u8]) -> String {
         format!("{}:{}", event, base64::encode(payload))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::FieldState;
+
+    fn round_trip(event: Event) {
+        let packet = Packet::new(event.clone());
+
+        let raw = packet.encode_raw();
+        assert_eq!(Packet::decode_raw(&raw).unwrap().event, event);
+
+        let bytes = packet.encode_binary();
+        assert_eq!(Packet::decode_binary(&bytes).unwrap().event, event);
+
+        // All encodings carry the same event
+        let encodings = [
+            Encoding::Text,
+            Encoding::Binary,
+            #[cfg(feature = "json")]
+            Encoding::Json,
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack,
+        ];
+        for encoding in encodings {
+            let frame = packet.encode_frame(encoding);
+            let decoded = Packet::decode_frame(&frame, encoding).unwrap();
+            assert_eq!(decoded.event, event);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(Event::Hello(Handshake {
+            version: PROTOCOL_VERSION,
+            features: Vec::new(),
+        }));
+        round_trip(Event::Welcome(Handshake {
+            version: 3,
+            features: vec![String::from("rooms"), String::from("lobby")],
+        }));
+        round_trip(Event::Session(Uuid::from_u128(7)));
+        round_trip(Event::Resume(Uuid::from_u128(7)));
+        round_trip(Event::ResumeFailed);
+        round_trip(Event::Nickname(String::from("oat")));
+        round_trip(Event::NicknameAccepted(String::from("oat")));
+        round_trip(Event::NicknameRejected(NicknameError::Taken));
+        round_trip(Event::NicknameRejected(NicknameError::InvalidCharacter));
+        round_trip(Event::JoinQueue);
+        round_trip(Event::LeaveQueue);
+        round_trip(Event::PlayBot(Difficulty::Hard));
+        round_trip(Event::NoOpponent(None));
+        round_trip(Event::NoOpponent(Some(Difficulty::Easy)));
+        round_trip(Event::CreateRoom);
+        round_trip(Event::RoomCreated(String::from("K7QF2M")));
+        round_trip(Event::JoinRoom(String::from("k7qf2m")));
+        round_trip(Event::LeaveRoom);
+        round_trip(Event::RoomError(RoomError::NotFound));
+        round_trip(Event::RoomError(RoomError::Full));
+        round_trip(Event::Spectate(Uuid::from_u128(0x1234_5678)));
+        round_trip(Event::StopSpectating);
+        round_trip(Event::GameNotFound);
+        round_trip(Event::SpectatorCount(3));
+        round_trip(Event::SubscribeLobby);
+        round_trip(Event::UnsubscribeLobby);
+        round_trip(Event::Lobby(Lobby {
+            games: Vec::new(),
+            queued: 0,
+            online: 1,
+        }));
+        round_trip(Event::Lobby(Lobby {
+            games: vec![
+                LobbyGame {
+                    id: Uuid::from_u128(1),
+                    horizontal: String::from("oat; two,"),
+                    vertical: String::new(),
+                    moves: 4,
+                    spectators: 2,
+                },
+                LobbyGame {
+                    id: Uuid::from_u128(2),
+                    horizontal: String::from("tick"),
+                    vertical: String::from("Bot"),
+                    moves: 0,
+                    spectators: 0,
+                },
+            ],
+            queued: 3,
+            online: 9,
+        }));
+        round_trip(Event::Start(Player::Horizontal));
+        round_trip(Event::Move(2, 1));
+        round_trip(Event::InvalidMove(MoveError::RepeatedLastMove));
+        round_trip(Event::InvalidMove(MoveError::NotYourTurn));
+        round_trip(Event::Winner(Player::Vertical));
+        round_trip(Event::OpponentDisconnected(60));
+        round_trip(Event::OpponentReconnected);
+        round_trip(Event::OpponentLeft);
+        round_trip(Event::Draw);
+        round_trip(Event::Error(ServerError::UnknownUser));
+        round_trip(Event::Error(ServerError::UnknownGame));
+        round_trip(Event::Error(ServerError::Spectating));
+
+        let mut board = [[FieldState::None; 3]; 3];
+        board[0][1] = FieldState::OccupiedByOne(Player::Horizontal);
+        board[1][1] = FieldState::OccupiedByOne(Player::Vertical);
+        board[2][0] = FieldState::Both;
+        round_trip(Event::State(board, Player::Horizontal));
+    }
+
+    #[test]
+    fn test_decode_invalid_payload() {
+        let decode =
+            |event, payload: &[u8]| Packet::decode_raw(&Packet::encode_data(event, payload)).err();
+        let invalid = |event: &str| Some(DecodeError::InvalidPayload(String::from(event)));
+
+        assert_eq!(decode("move", &[1]), invalid("move"));
+        assert_eq!(decode("move", &[1, 2, 3]), invalid("move"));
+        assert_eq!(decode("move", &[]), invalid("move"));
+        assert_eq!(decode("draw", &[0]), invalid("draw"));
+        assert_eq!(decode("winner", &[2]), invalid("winner"));
+        assert_eq!(decode("room_error", &[7]), invalid("room_error"));
+        assert_eq!(decode("spectate", &[16, 1, 2]), invalid("spectate"));
+        assert_eq!(decode("nickname", &[2, 0xff, 0xfe]), invalid("nickname"));
+        assert_eq!(decode("hello", &[1]), invalid("hello"));
+        assert_eq!(decode("no_opponent", &[1, 9]), invalid("no_opponent"));
+    }
+
+    #[test]
+    fn test_decode_invalid_packet() {
+        let decode = |raw| Packet::decode_raw(raw).err();
+
+        assert_eq!(decode("nickname"), Some(DecodeError::InvalidParts));
+        assert_eq!(decode("nickname:%%"), Some(DecodeError::InvalidEncoding));
+        assert_eq!(
+            decode("unknown:"),
+            Some(DecodeError::UnknownEvent(String::from("unknown")))
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_binary() {
+        let decode = |bytes: &[u8]| Packet::decode_binary(bytes).err();
+        let invalid = |tag: u8| Some(DecodeError::InvalidPayload(tag.to_string()));
+
+        assert_eq!(decode(&[]), Some(DecodeError::InvalidParts));
+        assert_eq!(
+            decode(&[255]),
+            Some(DecodeError::UnknownEvent(String::from("255")))
+        );
+        assert_eq!(decode(&[5, 2, 0xff, 0xfe]), invalid(5));
+        assert_eq!(decode(&[25, 1]), invalid(25));
+        assert_eq!(decode(&[25, 2, 1, 0]), invalid(25));
+    }
+
+    #[test]
+    fn test_text_and_binary_share_events() {
+        // Binary tags follow the order of the events
+        assert_eq!(
+            Packet::new(Event::Hello(Handshake {
+                version: PROTOCOL_VERSION,
+                features: Vec::new(),
+            }))
+            .encode_binary()[0],
+            0
+        );
+        assert_eq!(
+            Packet::new(Event::Move(2, 1)).encode_binary(),
+            vec![25, 2, 1]
+        );
+        assert_eq!(
+            Packet::new(Event::OpponentReconnected).encode_binary(),
+            vec![33]
+        );
+
+        // Text frames carry the same payload under the name of the event
+        assert_eq!(Packet::new(Event::Move(2, 1)).encode_raw(), "move:AgE=");
+        assert_eq!(Packet::new(Event::Draw).encode_raw(), "draw:");
+    }
+
+    #[test]
+    fn test_binary_payloads_are_native() {
+        // Coordinates are single bytes
+        assert_eq!(
+            Packet::new(Event::Move(2, 1)).encode_binary(),
+            vec![25, 2, 1]
+        );
+
+        // Uuids are their 16 bytes after the length
+        let id = Uuid::from_u128(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10);
+        let mut session = vec![2, 16];
+        session.extend_from_slice(id.as_bytes());
+        assert_eq!(Packet::new(Event::Session(id)).encode_binary(), session);
+
+        // Fields of the board take two bits each
+        let mut board = [[FieldState::None; 3]; 3];
+        board[0][0] = FieldState::OccupiedByOne(Player::Horizontal);
+        board[2][2] = FieldState::Both;
+        let state = Packet::new(Event::State(board, Player::Vertical)).encode_binary();
+        assert_eq!(state, vec![26, 0x81, 0x80, 0x0c, 1]);
+        assert_eq!(
+            Packet::decode_binary(&[26, 0x80, 0x80, 0x10, 1]).err(),
+            Some(DecodeError::InvalidPayload(String::from("26")))
+        );
+
+        // Payloads don't have to be valid UTF-8
+        let count = Packet::new(Event::SpectatorCount(255)).encode_binary();
+        assert!(String::from_utf8(count.clone()).is_err());
+        assert_eq!(
+            Packet::decode_binary(&count).unwrap().event,
+            Event::SpectatorCount(255)
+        );
+    }
+
+    #[test]
+    fn test_negotiated_encoding() {
+        let handshake = |features: &[&str]| Handshake {
+            version: PROTOCOL_VERSION,
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+        };
+
+        assert_eq!(Encoding::negotiated(&handshake(&[])), Encoding::Text);
+        assert_eq!(
+            Encoding::negotiated(&handshake(&["lobby", BINARY_FEATURE])),
+            Encoding::Binary
+        );
+
+        #[cfg(feature = "json")]
+        assert_eq!(
+            Encoding::negotiated(&handshake(&[BINARY_FEATURE, JSON_FEATURE])),
+            Encoding::Json
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let packet = Packet::new(Event::Move(2, 1));
+        assert_eq!(packet.encode_json(), r#"{"event":"move","payload":[2,1]}"#);
+
+        let packet = Packet::new(Event::Draw);
+        assert_eq!(packet.encode_json(), r#"{"event":"draw"}"#);
+
+        let decoded = Packet::decode_json(r#"{"event":"winner","payload":"vertical"}"#);
+        assert_eq!(decoded.unwrap().event, Event::Winner(Player::Vertical));
+
+        assert!(matches!(
+            Packet::decode_json(r#"{"event":"move","payload":[2]}"#),
+            Err(DecodeError::InvalidStructure(_))
+        ));
+        assert_eq!(
+            Packet::decode_binary_frame(&[], Encoding::Json).err(),
+            Some(DecodeError::InvalidMessage)
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_invalid() {
+        assert!(matches!(
+            Packet::decode_msgpack(&[0xc1]),
+            Err(DecodeError::InvalidStructure(_))
+        ));
+
+        let frame = Packet::new(Event::Draw).encode_frame(Encoding::Text);
+        assert_eq!(
+            Packet::decode_frame(&frame, Encoding::MessagePack).err(),
+            Some(DecodeError::InvalidMessage)
+        );
+    }
+}
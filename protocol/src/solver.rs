@@ -0,0 +1,232 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::game::{FieldState, Game, GameOutcome, Player};
+
+// Game-theoretic value for the player to move, with the number of moves until the game ends
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Value {
+    Win(u8),
+    Draw,
+    Loss(u8),
+}
+
+impl Value {
+    // Value for the player who made the move leading to this position
+    fn for_previous_player(self) -> Value {
+        match self {
+            Value::Win(moves) => Value::Loss(moves + 1),
+            Value::Draw => Value::Draw,
+            Value::Loss(moves) => Value::Win(moves + 1),
+        }
+    }
+
+    // Faster wins and slower losses are better
+    fn score(self) -> i16 {
+        match self {
+            Value::Win(moves) => 1000 - moves as i16,
+            Value::Draw => 0,
+            Value::Loss(moves) => -1000 + moves as i16,
+        }
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score().cmp(&other.score())
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Position packed into two bits per field, the player to move and the last round's field
+fn position_key(game: &Game) -> u32 {
+    let mut key = game
+        .board()
+        .iter()
+        .flatten()
+        .fold(0, |key, field| key << 2 | field_bits(*field));
+
+    key = key << 1 | (game.current_player() == Player::Vertical) as u32;
+
+    let previous_move = match game.previous_move() {
+        Some((row, col)) => 1 + row as u32 * 3 + col as u32,
+        None => 0,
+    };
+    key << 4 | previous_move
+}
+
+fn field_bits(field: FieldState) -> u32 {
+    match field {
+        FieldState::None => 0,
+        FieldState::OccupiedByOne(Player::Horizontal) => 1,
+        FieldState::OccupiedByOne(Player::Vertical) => 2,
+        FieldState::Both => 3,
+    }
+}
+
+// Perfect play solver with a transposition table shared between searches
+pub struct Solver {
+    table: HashMap<u32, Value>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self {
+            table: HashMap::new(),
+        }
+    }
+
+    // Solver knowing the value of every position reachable from the start
+    pub fn solved() -> Self {
+        let mut solver = Self::new();
+        solver.solve(&Game::new());
+        solver
+    }
+
+    pub fn solve(&mut self, game: &Game) -> Value {
+        self.search(&mut game.clone())
+    }
+
+    // Value of every legal move for the player making it
+    pub fn evaluate_moves(&mut self, game: &Game) -> Vec<((u8, u8), Value)> {
+        Self::rank_moves(game, |game| Some(self.search(game)))
+    }
+
+    pub fn best_move(&mut self, game: &Game) -> Option<(u8, u8)> {
+        Self::best(self.evaluate_moves(game))
+    }
+
+    // Best move from the table only, without searching, None if a move was never searched
+    pub fn known_best_move(&self, game: &Game) -> Option<(u8, u8)> {
+        let moves = Self::rank_moves(game, |game| self.table.get(&position_key(game)).copied());
+        if moves.len() < game.legal_moves().count() {
+            return None;
+        }
+        Self::best(moves)
+    }
+
+    // Legal moves with their value for the player making them, skipping unknown values
+    fn rank_moves(
+        game: &Game,
+        mut value: impl FnMut(&mut Game) -> Option<Value>,
+    ) -> Vec<((u8, u8), Value)> {
+        let mut game = game.clone();
+        let moves = game.legal_moves().collect::<Vec<_>>();
+
+        moves
+            .into_iter()
+            .filter_map(|coords| {
+                game.make_move(coords).unwrap();
+                let value = value(&mut game).map(Value::for_previous_player);
+                game.undo();
+                Some((coords, value?))
+            })
+            .collect()
+    }
+
+    fn best(moves: Vec<((u8, u8), Value)>) -> Option<(u8, u8)> {
+        moves
+            .into_iter()
+            .max_by_key(|(_, value)| *value)
+            .map(|(coords, _)| coords)
+    }
+
+    fn search(&mut self, game: &mut Game) -> Value {
+        let position = position_key(game);
+        if let Some(value) = self.table.get(&position) {
+            return *value;
+        }
+
+        let value = match game.outcome() {
+            GameOutcome::Win(_) => Value::Loss(0),
+            GameOutcome::Draw | GameOutcome::Stalemate(_) => Value::Draw,
+            GameOutcome::InProgress => {
+                let moves = game.legal_moves().collect::<Vec<_>>();
+                let mut best = Value::Loss(0);
+
+                for coords in moves {
+                    game.make_move(coords).unwrap();
+                    best = best.max(self.search(game).for_previous_player());
+                    game.undo();
+                }
+
+                best
+            }
+        };
+
+        self.table.insert(position, value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{play, TOP_ROW_WIN};
+
+    #[test]
+    fn test_value_order() {
+        assert!(Value::Win(1) > Value::Win(3));
+        assert!(Value::Win(9) > Value::Draw);
+        assert!(Value::Draw > Value::Loss(9));
+        assert!(Value::Loss(3) > Value::Loss(1));
+    }
+
+    #[test]
+    fn test_finished_positions() {
+        let mut solver = Solver::new();
+
+        let game = play(&TOP_ROW_WIN);
+        assert_eq!(solver.solve(&game), Value::Loss(0));
+        assert_eq!(solver.best_move(&game), None);
+    }
+
+    #[test]
+    fn test_unknown_positions() {
+        let solver = Solver::new();
+        assert_eq!(solver.known_best_move(&Game::new()), None);
+    }
+
+    #[test]
+    fn test_winning_move() {
+        let mut solver = Solver::new();
+
+        // Vertical completes the top row with (0, 2)
+        let game = play(&TOP_ROW_WIN[..5]);
+        assert_eq!(solver.solve(&game), Value::Win(1));
+        assert_eq!(solver.best_move(&game), Some((0, 2)));
+    }
+
+    // Test the second player wins the starting position under perfect play
+    #[test]
+    fn test_solve_start() {
+        let mut solver = Solver::new();
+        let mut game = Game::new();
+
+        let value = solver.solve(&game);
+        assert!(matches!(value, Value::Loss(_)));
+
+        // Perfect play from both sides ends with the vertical player winning,
+        // every position on the way is known without searching again
+        while let Some(coords) = solver.best_move(&game) {
+            assert_eq!(solver.known_best_move(&game), Some(coords));
+            game.make_move(coords).unwrap();
+        }
+        let moves = game.history().count() as u8;
+        assert_eq!(value, Value::Loss(moves));
+        assert_eq!(
+            game.check_win().map(|win| win.player),
+            Some(Player::Vertical)
+        );
+    }
+}
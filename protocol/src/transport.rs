@@ -0,0 +1,66 @@
+// Adapters between packets and the message types of websocket libraries
+#[cfg(feature = "tungstenite")]
+use tungstenite::Message;
+#[cfg(feature = "ws_stream_wasm")]
+use ws_stream_wasm::WsMessage;
+
+#[cfg(any(feature = "tungstenite", feature = "ws_stream_wasm"))]
+use crate::packet::{DecodeError, Encoding, Frame, Packet};
+
+#[cfg(feature = "tungstenite")]
+impl Packet {
+    // Control frames like ping and close are not packets
+    pub fn decode_message(message: &Message, encoding: Encoding) -> Result<Self, DecodeError> {
+        match message {
+            Message::Text(raw) => Self::decode_text_frame(raw, encoding),
+            Message::Binary(bytes) => Self::decode_binary_frame(bytes, encoding),
+            _ => Err(DecodeError::InvalidMessage),
+        }
+    }
+
+    pub fn encode_message(&self, encoding: Encoding) -> Message {
+        match self.encode_frame(encoding) {
+            Frame::Text(raw) => Message::Text(raw),
+            Frame::Binary(bytes) => Message::Binary(bytes),
+        }
+    }
+}
+
+#[cfg(feature = "ws_stream_wasm")]
+impl Packet {
+    pub fn decode_ws_message(message: &WsMessage, encoding: Encoding) -> Result<Self, DecodeError> {
+        match message {
+            WsMessage::Text(raw) => Self::decode_text_frame(raw, encoding),
+            WsMessage::Binary(bytes) => Self::decode_binary_frame(bytes, encoding),
+        }
+    }
+
+    pub fn encode_ws_message(&self, encoding: Encoding) -> WsMessage {
+        match self.encode_frame(encoding) {
+            Frame::Text(raw) => WsMessage::Text(raw),
+            Frame::Binary(bytes) => WsMessage::Binary(bytes),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tungstenite"))]
+mod tests {
+    use super::*;
+    use crate::packet::Event;
+
+    #[test]
+    fn test_tungstenite() {
+        for encoding in [Encoding::Text, Encoding::Binary] {
+            let message = Packet::new(Event::Move(2, 1)).encode_message(encoding);
+            assert_eq!(message.is_binary(), encoding == Encoding::Binary);
+
+            let decoded = Packet::decode_message(&message, encoding).unwrap();
+            assert_eq!(decoded.event, Event::Move(2, 1));
+        }
+
+        assert_eq!(
+            Packet::decode_message(&Message::Ping(Vec::new()), Encoding::Text).err(),
+            Some(DecodeError::InvalidMessage)
+        );
+    }
+}
@@ -1,113 +1,1955 @@
-use log::{debug, info};
+use futures_channel::mpsc::UnboundedSender;
+use log::{debug, info, warn};
 use std::{
     collections::HashMap,
+    error::Error,
+    fmt,
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
 };
+use tokio::time::Instant;
 use uuid::Uuid;
 
-use crate::game::Game;
-use crate::user::User;
-use tickoattwo::packet::{Event, Packet};
+use crate::bot::Bot;
+use crate::nickname::{self, NicknamePolicy};
+use crate::queue::Queue;
+use crate::room::{self, Room};
+use crate::user::{User, UserId};
+use tickoattwo::game::{Game, GameOutcome, Player};
+use tickoattwo::packet::{Difficulty, Event, Lobby, LobbyGame, Packet, RoomError, ServerError};
+
+// Participant of a game, either a connected user or a server side bot
+pub enum Seat {
+    User(UserId),
+    Bot(Bot),
+}
+
+impl Seat {
+    fn user(&self) -> Option<&UserId> {
+        match self {
+            Seat::User(user_id) => Some(user_id),
+            Seat::Bot(_) => None,
+        }
+    }
+}
+
+// What happens to a user waiting longer than the queue timeout
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QueueFallback {
+    // Offer a bot game of the given difficulty
+    OfferBot(Difficulty),
+    // Only tell the user no opponent was found
+    Notify,
+}
+
+pub struct Config {
+    pub queue_timeout: Duration,
+    pub queue_fallback: QueueFallback,
+    // How long the game of a disconnected player is kept for them to resume
+    pub reconnect_grace: Duration,
+    pub nickname_policy: NicknamePolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            queue_timeout: Duration::from_secs(30),
+            queue_fallback: QueueFallback::OfferBot(Difficulty::Medium),
+            reconnect_grace: Duration::from_secs(60),
+            nickname_policy: NicknamePolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BackendError {
+    // User left before the event was handled
+    UnknownUser(UserId),
+    // Game of the user no longer exists
+    UnknownGame(Uuid),
+}
+
+impl BackendError {
+    // Reason as reported to the client
+    fn server_error(&self) -> ServerError {
+        match self {
+            BackendError::UnknownUser(_) => ServerError::UnknownUser,
+            BackendError::UnknownGame(_) => ServerError::UnknownGame,
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::UnknownUser(user_id) => write!(f, "unknown user: {}", user_id),
+            BackendError::UnknownGame(game_id) => write!(f, "unknown game: {}", game_id),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+// Lock a store, recovering its data if a task panicked while holding it
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("Recovered poisoned lock");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+// Game with the seats of both players and the users watching it
+struct ActiveGame {
+    game: Game,
+    horizontal: Seat,
+    vertical: Seat,
+    spectators: Vec<UserId>,
+}
+
+impl ActiveGame {
+    // Connected users playing the game
+    fn players(&self) -> impl Iterator<Item = &UserId> {
+        [self.horizontal.user(), self.vertical.user()]
+            .into_iter()
+            .flatten()
+    }
+
+    // Every connected user receiving updates of the game
+    fn receivers(&self) -> impl Iterator<Item = &UserId> {
+        self.players().chain(self.spectators.iter())
+    }
+}
+
+type GameStore = HashMap<Uuid, ActiveGame>;
 
 pub struct Backend {
-    // Single slot waiting room
-    queue: Option<SocketAddr>,
+    config: Config,
+
+    // Users waiting for an opponent
+    queue: Queue,
 
     // User store
-    users: Arc<Mutex<HashMap<SocketAddr, User>>>,
+    users: Arc<Mutex<HashMap<UserId, User>>>,
+
+    // Outgoing packet channels of connected users
+    senders: HashMap<UserId, UnboundedSender<Packet>>,
 
     // Game state store
-    games: Arc<Mutex<HashMap<Uuid, (Game, SocketAddr, SocketAddr)>>>,
+    games: Arc<Mutex<GameStore>>,
+
+    // Private rooms by join code
+    rooms: Arc<Mutex<HashMap<String, Room>>>,
+
+    // Lobby as last pushed to subscribed users
+    lobby: Option<Lobby>,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Backend {
     pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Self::with_queue(config, Queue::new())
+    }
+
+    pub fn with_queue(config: Config, queue: Queue) -> Self {
         Self {
-            queue: None,
+            config,
+            queue,
             users: Arc::new(Mutex::new(HashMap::new())),
+            senders: HashMap::new(),
             games: Arc::new(Mutex::new(HashMap::new())),
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+            lobby: None,
         }
     }
 
-    pub fn user_join(&mut self, new_user_id: &SocketAddr) {
-        let mut users = self.users.lock().unwrap();
+    // Register a new connection, returns the id identifying it from now on
+    pub fn user_join(&mut self, addr: &SocketAddr, sender: UnboundedSender<Packet>) -> UserId {
+        let new_user_id = UserId::new();
+        self.senders.insert(new_user_id, sender);
 
-        let new_user = User::new();
-        users.insert(new_user_id.clone(), new_user);
+        let mut users = lock(&self.users);
+
+        let new_user = User::new(*addr);
+        let session = new_user.session;
+        users.insert(new_user_id, new_user);
 
         std::mem::drop(users);
 
-        if let Some(queue_user_id) = self.queue {
-            self.queue = None;
-            self.start_game(&queue_user_id, new_user_id);
+        debug!("Joined: {} ({})", new_user_id, addr);
+        self.send(&new_user_id, Packet::new(Event::Session(session)));
+
+        // Users are only queued once they ask for it, a reconnecting one resumes instead
+        self.update_lobby();
+
+        new_user_id
+    }
+
+    fn join_queue(&mut self, user_id: &UserId) -> Result<(), BackendError> {
+        if !self.release_finished_game(user_id)? {
+            debug!("Queue requested while playing ({})", user_id);
+            return Ok(());
+        }
+
+        self.leave_room(user_id);
+        if self.queue.join(user_id) {
+            debug!("Joined queue: {}", user_id);
+        }
+
+        while let Some((user_id1, user_id2)) = self.queue.next_pair() {
+            if let Err(err) = self.start_game(Seat::User(user_id1), Seat::User(user_id2)) {
+                warn!("Failed to start queued game: {}", err);
+
+                // Whoever is still connected keeps waiting
+                let users = lock(&self.users);
+                for user_id in [user_id1, user_id2] {
+                    if users.contains_key(&user_id) {
+                        self.queue.join(&user_id);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn leave_queue(&mut self, user_id: &UserId) {
+        if let Some(waited) = self.queue.leave(user_id) {
+            debug!("Left queue after {:?}: {}", waited, user_id);
+        }
+    }
+
+    fn create_room(&mut self, user_id: &UserId) -> Result<(), BackendError> {
+        if !self.release_finished_game(user_id)? {
+            debug!("Room requested while playing ({})", user_id);
+            return Ok(());
+        }
+
+        self.leave_queue(user_id);
+        self.leave_room(user_id);
+
+        let mut rooms = lock(&self.rooms);
+        let code = room::generate_code(|code| rooms.contains_key(code));
+        Self::user_mut(&mut lock(&self.users), user_id)?.room = Some(code.clone());
+        rooms.insert(code.clone(), Room::new(user_id));
+        std::mem::drop(rooms);
+
+        info!("Created room {}: {}", code, user_id);
+
+        self.send(user_id, Packet::new(Event::RoomCreated(code)));
+        Ok(())
+    }
+
+    fn join_room(&mut self, user_id: &UserId, code: &str) -> Result<(), BackendError> {
+        let code = room::normalize_code(code);
+
+        let room_error = match lock(&self.rooms).get(&code) {
+            Some(room) if room.members.contains(user_id) => return Ok(()),
+            Some(room) if room.is_full() => Some(RoomError::Full),
+            Some(_) => None,
+            None => Some(RoomError::NotFound),
+        };
+        if let Some(err) = room_error {
+            self.send(user_id, Packet::new(Event::RoomError(err)));
+            return Ok(());
+        }
+
+        if !self.release_finished_game(user_id)? {
+            debug!("Room requested while playing ({})", user_id);
+            return Ok(());
+        }
+
+        self.leave_queue(user_id);
+        self.leave_room(user_id);
+
+        // Room only pairs its members and is gone once their game starts.
+        // It may also have expired while leaving the previous one.
+        let room = lock(&self.rooms).remove(&code);
+        let creator = match room {
+            Some(room) => room.members[0],
+            None => {
+                self.send(user_id, Packet::new(Event::RoomError(RoomError::NotFound)));
+                return Ok(());
+            }
+        };
+        Self::user_mut(&mut lock(&self.users), &creator)?.room = None;
+
+        info!("Joined room {}: {}", code, user_id);
+        debug!("Removed room: {}", code);
+
+        // Creator of the room has the first move
+        self.start_game(Seat::User(creator), Seat::User(*user_id))
+    }
+
+    // Room expires once its creator has left before anyone joined
+    fn leave_room(&mut self, user_id: &UserId) {
+        let code = match lock(&self.users).get_mut(user_id) {
+            Some(user) => user.room.take(),
+            None => None,
+        };
+        let code = match code {
+            Some(code) => code,
+            None => return,
+        };
+
+        let mut rooms = lock(&self.rooms);
+        if let Some(room) = rooms.get_mut(&code) {
+            room.members.retain(|member| member != user_id);
+            debug!("Left room {}: {}", code, user_id);
+
+            if room.is_empty() {
+                rooms.remove(&code);
+                debug!("Removed room: {}", code);
+            }
+        }
+    }
+
+    // Apply the queue fallback to users waiting longer than the timeout
+    pub fn check_queue_timeouts(&mut self) {
+        let offer = match self.config.queue_fallback {
+            QueueFallback::OfferBot(difficulty) => Some(difficulty),
+            QueueFallback::Notify => None,
+        };
+
+        for user_id in self.queue.take_timed_out(self.config.queue_timeout) {
+            info!("No opponent found in time: {}", user_id);
+            self.send(&user_id, Packet::new(Event::NoOpponent(offer)));
+        }
+
+        self.update_lobby();
+    }
+
+    // Snapshot of running games, queued and online users
+    fn lobby(&self) -> Lobby {
+        let users = lock(&self.users);
+        let games = lock(&self.games);
+
+        let nickname = |seat: &Seat| match seat {
+            Seat::User(user_id) => users
+                .get(user_id)
+                .map(|user| user.username.clone())
+                .unwrap_or_default(),
+            Seat::Bot(_) => String::from("Bot"),
+        };
+
+        let mut lobby_games = games
+            .iter()
+            .filter(|(_, entry)| !entry.game.is_finished())
+            .map(|(game_id, entry)| LobbyGame {
+                id: *game_id,
+                horizontal: nickname(&entry.horizontal),
+                vertical: nickname(&entry.vertical),
+                moves: entry.game.history().count() as u32,
+                spectators: entry.spectators.len() as u32,
+            })
+            .collect::<Vec<_>>();
+        lobby_games.sort_by_key(|game| game.id);
+
+        Lobby {
+            games: lobby_games,
+            queued: self.queue.len() as u32,
+            online: self.senders.len() as u32,
+        }
+    }
+
+    // Push the lobby to subscribed users if it changed since the last push
+    fn update_lobby(&mut self) {
+        let lobby = self.lobby();
+        if self.lobby.as_ref() == Some(&lobby) {
+            return;
+        }
+
+        for (user_id, user) in lock(&self.users).iter() {
+            if user.lobby_subscribed {
+                self.send(user_id, Packet::new(Event::Lobby(lobby.clone())));
+            }
+        }
+
+        self.lobby = Some(lobby);
+    }
+
+    fn subscribe_lobby(&mut self, user_id: &UserId, subscribed: bool) {
+        if let Some(user) = lock(&self.users).get_mut(user_id) {
+            user.lobby_subscribed = subscribed;
+        }
+
+        if subscribed {
+            self.send(user_id, Packet::new(Event::Lobby(self.lobby())));
+        }
+    }
+
+    // Detach the user from a finished game, returns false if still playing
+    fn release_finished_game(&mut self, user_id: &UserId) -> Result<bool, BackendError> {
+        let mut users = lock(&self.users);
+        let mut games = lock(&self.games);
+
+        let user = Self::user_mut(&mut users, user_id)?;
+        let game_id = match user.game {
+            Some(game_id) => game_id,
+            None => return Ok(true),
+        };
+
+        if let Some(entry) = games.get(&game_id) {
+            if !entry.game.is_finished() {
+                return Ok(false);
+            }
+
+            user.game = None;
+
+            // Last user to leave cleans up the game
+            let attached = entry
+                .players()
+                .any(|other| users.get(other).and_then(|other| other.game) == Some(game_id));
+            if !attached {
+                if let Some(entry) = games.remove(&game_id) {
+                    Self::detach_spectators(&mut users, &entry);
+                    debug!("Removed game: {}", game_id);
+                }
+            }
         } else {
-            self.queue = Some(new_user_id.clone());
+            user.game = None;
         }
+
+        Ok(true)
+    }
+
+    fn user_mut<'a>(
+        users: &'a mut HashMap<UserId, User>,
+        user_id: &UserId,
+    ) -> Result<&'a mut User, BackendError> {
+        users
+            .get_mut(user_id)
+            .ok_or(BackendError::UnknownUser(*user_id))
     }
 
-    pub fn user_leave(&mut self, user_id: &SocketAddr) {
-        let mut users = self.users.lock().unwrap();
-        let mut games = self.games.lock().unwrap();
+    pub fn user_leave(&mut self, user_id: &UserId) {
+        self.senders.remove(user_id);
+        self.leave_queue(user_id);
+        self.leave_room(user_id);
+        self.stop_spectating(user_id);
+
+        // Keep a running game for the player to resume from a new connection
+        let mut users = lock(&self.users);
+        let games = lock(&self.games);
+        if let Some(user) = users.get_mut(user_id) {
+            let running = user
+                .game
+                .and_then(|game_id| games.get(&game_id))
+                .filter(|entry| !entry.game.is_finished());
+            if let Some(entry) = running {
+                user.disconnected_at = Some(Instant::now());
+                info!("Waiting for player to reconnect: {}", user_id);
+
+                // Everyone else in the game learns how long they may have to wait
+                let seconds = self.config.reconnect_grace.as_secs() as u32;
+                let packets = entry
+                    .receivers()
+                    .filter(|receiver| *receiver != user_id)
+                    .map(|receiver| {
+                        let event = Event::OpponentDisconnected(seconds);
+                        (*receiver, Packet::new(event))
+                    })
+                    .collect();
+
+                std::mem::drop(games);
+                std::mem::drop(users);
+                self.send_all(packets);
+                self.update_lobby();
+                return;
+            }
+        }
+        std::mem::drop(games);
+        std::mem::drop(users);
+
+        self.remove_user(user_id);
+    }
+
+    // Remove disconnected players who did not resume within the grace period
+    pub fn check_reconnect_timeouts(&mut self) {
+        let expired = lock(&self.users)
+            .iter()
+            .filter(|(_, user)| {
+                user.disconnected_at
+                    .is_some_and(|since| since.elapsed() >= self.config.reconnect_grace)
+            })
+            .map(|(user_id, _)| *user_id)
+            .collect::<Vec<_>>();
+
+        for user_id in expired {
+            info!("Player did not reconnect in time: {}", user_id);
+            self.remove_user(&user_id);
+        }
+    }
+
+    // Take over the session of a disconnected user and resync its game
+    fn resume(&mut self, user_id: &UserId, session: Uuid) -> Result<(), BackendError> {
+        let old_user_id = lock(&self.users)
+            .iter()
+            .find(|(_, user)| user.session == session && user.disconnected_at.is_some())
+            .map(|(old_user_id, _)| *old_user_id);
+        let old_user_id = match old_user_id {
+            Some(old_user_id) => old_user_id,
+            None => {
+                self.send(user_id, Packet::new(Event::ResumeFailed));
+                return Ok(());
+            }
+        };
+
+        if !self.release_finished_game(user_id)? {
+            debug!("Resume requested while playing ({})", user_id);
+            self.send(user_id, Packet::new(Event::ResumeFailed));
+            return Ok(());
+        }
+
+        self.leave_queue(user_id);
+        self.leave_room(user_id);
+        self.stop_spectating(user_id);
+
+        let mut users = lock(&self.users);
+        let mut games = lock(&self.games);
+
+        // Resumed user replaces the fresh one of the new connection
+        let mut user = match users.remove(&old_user_id) {
+            Some(user) => user,
+            None => {
+                std::mem::drop(games);
+                std::mem::drop(users);
+                self.send(user_id, Packet::new(Event::ResumeFailed));
+                return Ok(());
+            }
+        };
+        user.disconnected_at = None;
+        let game_id = user.game;
+        users.insert(*user_id, user);
+        info!("Resumed session of {}: {}", old_user_id, user_id);
+
+        let mut packets = vec![(*user_id, Packet::new(Event::Session(session)))];
 
-        if Some(user_id) == self.queue.as_ref() {
-            self.queue = None;
-            debug!("Removed user from queue: {}", user_id);
+        if let Some(entry) = game_id.and_then(|game_id| games.get_mut(&game_id)) {
+            for seat in [&mut entry.horizontal, &mut entry.vertical] {
+                if seat.user() == Some(&old_user_id) {
+                    *seat = Seat::User(*user_id);
+                }
+            }
+
+            packets.extend(
+                entry
+                    .receivers()
+                    .filter(|receiver| *receiver != user_id)
+                    .map(|receiver| (*receiver, Packet::new(Event::OpponentReconnected))),
+            );
+
+            let player = if entry.horizontal.user() == Some(user_id) {
+                Player::Horizontal
+            } else {
+                Player::Vertical
+            };
+
+            let count = entry.spectators.len() as u32;
+            let events = [Event::Start(player)]
+                .into_iter()
+                .chain(Self::state_events(&entry.game))
+                .chain([Event::SpectatorCount(count)]);
+            packets.extend(events.map(|event| (*user_id, Packet::new(event))));
         }
 
+        std::mem::drop(games);
+        std::mem::drop(users);
+        self.send_all(packets);
+        Ok(())
+    }
+
+    fn remove_user(&mut self, user_id: &UserId) {
+        let mut users = lock(&self.users);
+        let mut games = lock(&self.games);
+
+        let mut requeue = Vec::new();
+
         if let Some(user) = users.remove(user_id) {
             debug!("Removed user: {}", user_id);
             if let Some(game_id) = &user.game {
-                if let Some(game) = games.remove(game_id) {
+                if let Some(entry) = games.remove(game_id) {
                     debug!("Removed game: {}", game_id);
-                    let mut other_player: Option<SocketAddr> = None;
 
-                    if &game.1 == user_id {
-                        other_player = Some(game.2);
+                    // Abandoned game goes to the player who stayed
+                    let winner = match entry.horizontal.user() {
+                        Some(horizontal) if horizontal == user_id => Player::Vertical,
+                        _ => Player::Horizontal,
+                    };
+                    let abandoned = !entry.game.is_finished();
+                    if abandoned {
+                        info!("Game abandoned, won by {:?}: {}", winner, game_id);
                     }
 
-                    if &game.2 == user_id {
-                        other_player = Some(game.1);
+                    // Other player may queue again if still connected, a disconnected
+                    // one is left to its own grace period
+                    for other_player in entry.players() {
+                        if let Some(other) = users.get_mut(other_player) {
+                            if other.game.as_ref() == Some(game_id) {
+                                other.game = None;
+                                debug!("Detached other player: {}", other_player);
+
+                                if abandoned && other.disconnected_at.is_none() {
+                                    self.send(other_player, Packet::new(Event::OpponentLeft));
+                                    self.send(other_player, Packet::new(Event::Winner(winner)));
+                                    requeue.push(*other_player);
+                                }
+                            }
+                        }
                     }
 
-                    if let Some(other_player) = &other_player {
-                        users.remove(other_player);
-                        debug!("Removed other player: {}", other_player);
+                    Self::detach_spectators(&mut users, &entry);
+                    for spectator in &entry.spectators {
+                        if abandoned {
+                            self.send(spectator, Packet::new(Event::Winner(winner)));
+                        }
+                        self.send(spectator, Packet::new(Event::GameNotFound));
                     }
                 }
             }
         }
+
+        std::mem::drop(games);
+        std::mem::drop(users);
+
+        self.leave_queue(user_id);
+        for other_player in requeue {
+            if let Err(err) = self.join_queue(&other_player) {
+                warn!("Failed to queue remaining player: {}", err);
+            }
+        }
+        self.update_lobby();
+    }
+
+    fn detach_spectators(users: &mut HashMap<UserId, User>, entry: &ActiveGame) {
+        for spectator in &entry.spectators {
+            if let Some(user) = users.get_mut(spectator) {
+                user.spectating = None;
+            }
+        }
+    }
+
+    fn spectate(&mut self, user_id: &UserId, game_id: Uuid) -> Result<(), BackendError> {
+        if !self.release_finished_game(user_id)? {
+            debug!("Spectating requested while playing ({})", user_id);
+            return Ok(());
+        }
+
+        self.stop_spectating(user_id);
+
+        let mut users = lock(&self.users);
+        let mut games = lock(&self.games);
+
+        let entry = match games.get_mut(&game_id) {
+            Some(entry) => entry,
+            None => {
+                std::mem::drop(games);
+                std::mem::drop(users);
+                self.send(user_id, Packet::new(Event::GameNotFound));
+                return Ok(());
+            }
+        };
+
+        Self::user_mut(&mut users, user_id)?.spectating = Some(game_id);
+        entry.spectators.push(*user_id);
+        debug!("Spectating game {}: {}", game_id, user_id);
+
+        // Catch up on the current board before following the moves
+        let mut packets = Self::state_events(&entry.game)
+            .into_iter()
+            .map(|event| (*user_id, Packet::new(event)))
+            .collect::<Vec<_>>();
+        packets.extend(Self::spectator_count_packets(entry));
+
+        std::mem::drop(games);
+        std::mem::drop(users);
+        self.send_all(packets);
+        Ok(())
+    }
+
+    fn stop_spectating(&mut self, user_id: &UserId) {
+        let game_id = match lock(&self.users)
+            .get_mut(user_id)
+            .and_then(|user| user.spectating.take())
+        {
+            Some(game_id) => game_id,
+            None => return,
+        };
+
+        let mut games = lock(&self.games);
+        if let Some(entry) = games.get_mut(&game_id) {
+            entry.spectators.retain(|spectator| spectator != user_id);
+            debug!("Stopped spectating game {}: {}", game_id, user_id);
+
+            let packets = Self::spectator_count_packets(entry);
+            std::mem::drop(games);
+            self.send_all(packets);
+        }
     }
 
-    fn start_game(&mut self, user_id1: &SocketAddr, user_id2: &SocketAddr) {
+    fn spectator_count_packets(entry: &ActiveGame) -> Vec<(UserId, Packet)> {
+        let count = entry.spectators.len() as u32;
+        entry
+            .receivers()
+            .map(|user_id| (*user_id, Packet::new(Event::SpectatorCount(count))))
+            .collect()
+    }
+
+    fn start_game(&mut self, horizontal: Seat, vertical: Seat) -> Result<(), BackendError> {
         let game = Game::new();
         let game_id = Uuid::new_v4();
-        self.games
-            .lock()
-            .unwrap()
-            .insert(game_id.clone(), (game, user_id1.clone(), user_id2.clone()));
 
-        info!("Started new game: {} ({}, {})", game_id, user_id1, user_id2);
+        // Both users must still be connected before any is assigned
+        let users = lock(&self.users);
+        for user_id in [horizontal.user(), vertical.user()].into_iter().flatten() {
+            if !users.contains_key(user_id) {
+                return Err(BackendError::UnknownUser(*user_id));
+            }
+        }
+        std::mem::drop(users);
+
+        info!(
+            "Started new game: {} ({:?}, {:?})",
+            game_id,
+            horizontal.user(),
+            vertical.user()
+        );
+
+        for user_id in [horizontal.user(), vertical.user()].into_iter().flatten() {
+            self.stop_spectating(user_id);
+        }
 
         // Assign users to game
-        let mut users = self.users.lock().unwrap();
-        let user1 = users.get_mut(user_id1).unwrap();
-        user1.game = Some(game_id.clone());
-        let user2 = users.get_mut(user_id2).unwrap();
-        user2.game = Some(game_id.clone());
+        let mut users = lock(&self.users);
+        for (seat, player) in [
+            (&horizontal, Player::Horizontal),
+            (&vertical, Player::Vertical),
+        ] {
+            if let Some(user_id) = seat.user() {
+                Self::user_mut(&mut users, user_id)?.game = Some(game_id);
+                self.send(user_id, Packet::new(Event::Start(player)));
+            }
+        }
+        std::mem::drop(users);
+
+        let entry = ActiveGame {
+            game,
+            horizontal,
+            vertical,
+            spectators: Vec::new(),
+        };
+        lock(&self.games).insert(game_id, entry);
+
+        // Bot might have the first move
+        let packets = self.play_bots(&game_id);
+        self.send_all(packets);
+        Ok(())
+    }
+
+    fn start_bot_game(
+        &mut self,
+        user_id: &UserId,
+        difficulty: Difficulty,
+    ) -> Result<(), BackendError> {
+        if !self.release_finished_game(user_id)? {
+            debug!("Bot game requested while playing ({})", user_id);
+            return Ok(());
+        }
+
+        self.leave_queue(user_id);
+        self.leave_room(user_id);
+        info!("Starting bot game ({:?}) for {}", difficulty, user_id);
+        self.start_game(Seat::User(*user_id), Seat::Bot(Bot::new(difficulty)))
+    }
+
+    // Push a packet to a connected user
+    pub fn send(&self, user_id: &UserId, packet: Packet) {
+        match self.senders.get(user_id) {
+            Some(sender) => {
+                if sender.unbounded_send(packet).is_err() {
+                    warn!("Failed to queue packet for {}", user_id);
+                }
+            }
+            None => warn!("Dropped packet for unknown user {}", user_id),
+        }
     }
 
-    pub fn dispatch_event(&mut self, event: Event, user_id: &SocketAddr) -> Option<Packet> {
+    fn send_all(&self, packets: Vec<(UserId, Packet)>) {
+        for (user_id, packet) in packets {
+            self.send(&user_id, packet);
+        }
+    }
+
+    // Handle an event of a client, errors are reported to the client
+    pub fn dispatch_event(&mut self, event: Event, user_id: &UserId) {
         debug!("Received event: {:?} ({})", event, user_id);
 
-        match event {
-            Event::Nickname(username) => {
-                let mut users = self.users.lock().unwrap();
-                let user = users.get_mut(user_id).unwrap();
-                user.username = username;
+        if let Err(err) = self.handle_event(event, user_id) {
+            warn!("Failed to handle event ({}): {}", user_id, err);
+            self.send(user_id, Packet::new(Event::Error(err.server_error())));
+        }
+
+        self.update_lobby();
+    }
+
+    fn handle_event(&mut self, event: Event, user_id: &UserId) -> Result<(), BackendError> {
+        // Event might arrive after the user has left
+        if !lock(&self.users).contains_key(user_id) {
+            return Err(BackendError::UnknownUser(*user_id));
+        }
+
+        let packets = match event {
+            Event::Resume(session) => {
+                self.resume(user_id, session)?;
+                Vec::new()
+            }
+            Event::Nickname(nickname) => self.set_nickname(user_id, &nickname)?,
+            Event::JoinQueue => {
+                self.join_queue(user_id)?;
+                Vec::new()
+            }
+            Event::LeaveQueue => {
+                self.leave_queue(user_id);
+                Vec::new()
+            }
+            Event::PlayBot(difficulty) => {
+                self.start_bot_game(user_id, difficulty)?;
+                Vec::new()
+            }
+            Event::CreateRoom => {
+                self.create_room(user_id)?;
+                Vec::new()
+            }
+            Event::JoinRoom(code) => {
+                self.join_room(user_id, &code)?;
+                Vec::new()
+            }
+            Event::LeaveRoom => {
+                self.leave_room(user_id);
+                Vec::new()
+            }
+            Event::Spectate(game_id) => {
+                self.spectate(user_id, game_id)?;
+                Vec::new()
+            }
+            Event::StopSpectating => {
+                self.stop_spectating(user_id);
+                Vec::new()
+            }
+            Event::SubscribeLobby => {
+                self.subscribe_lobby(user_id, true);
+                Vec::new()
+            }
+            Event::UnsubscribeLobby => {
+                self.subscribe_lobby(user_id, false);
+                Vec::new()
+            }
+            Event::Move(row, col) => self.make_move(user_id, (row, col))?,
+            _ => {
+                debug!("Unexpected event from client ({})", user_id);
+                Vec::new()
+            }
+        };
+
+        self.send_all(packets);
+        Ok(())
+    }
+
+    fn set_nickname(
+        &mut self,
+        user_id: &UserId,
+        nickname: &str,
+    ) -> Result<Vec<(UserId, Packet)>, BackendError> {
+        let mut users = lock(&self.users);
+
+        let taken = |nickname: &str| {
+            users.iter().any(|(other_id, other)| {
+                other_id != user_id && nickname::same_nickname(&other.username, nickname)
+            })
+        };
+        let event = match self.config.nickname_policy.check(nickname, taken) {
+            Ok(nickname) => {
+                debug!("Nickname set to {:?}: {}", nickname, user_id);
+                Self::user_mut(&mut users, user_id)?.username = nickname.clone();
+                Event::NicknameAccepted(nickname)
+            }
+            Err(err) => {
+                debug!("Nickname {:?} rejected ({}): {}", nickname, err, user_id);
+                Event::NicknameRejected(err)
+            }
+        };
+
+        Ok(vec![(*user_id, Packet::new(event))])
+    }
+
+    fn make_move(
+        &mut self,
+        user_id: &UserId,
+        coords: (u8, u8),
+    ) -> Result<Vec<(UserId, Packet)>, BackendError> {
+        let (game_id, spectating) = match lock(&self.users).get(user_id) {
+            Some(user) => (user.game, user.spectating.is_some()),
+            None => return Err(BackendError::UnknownUser(*user_id)),
+        };
+        let game_id = match game_id {
+            Some(game_id) => game_id,
+            None if spectating => {
+                let err = ServerError::Spectating;
+                return Ok(vec![(*user_id, Packet::new(Event::Error(err)))]);
+            }
+            None => return Ok(Vec::new()),
+        };
+
+        let mut games = lock(&self.games);
+        let entry = games
+            .get_mut(&game_id)
+            .ok_or(BackendError::UnknownGame(game_id))?;
+
+        let player = if entry.horizontal.user() == Some(user_id) {
+            Player::Horizontal
+        } else {
+            Player::Vertical
+        };
+
+        if let Err(err) = entry.game.make_move_as(player, coords) {
+            return Ok(vec![(*user_id, Packet::new(Event::InvalidMove(err)))]);
+        }
+
+        debug!("Move {:?} by {:?} in game {}", coords, player, game_id);
+
+        let mut packets = Self::move_packets(&game_id, entry);
+        std::mem::drop(games);
+
+        packets.extend(self.play_bots(&game_id));
+        Ok(packets)
+    }
+
+    // Let bots move until a user is to move or the game is over
+    fn play_bots(&mut self, game_id: &Uuid) -> Vec<(UserId, Packet)> {
+        let mut games = lock(&self.games);
+        let entry = match games.get_mut(game_id) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+
+        let mut packets = Vec::new();
+
+        while !entry.game.is_finished() {
+            let seat = match entry.game.current_player() {
+                Player::Horizontal => &entry.horizontal,
+                Player::Vertical => &entry.vertical,
+            };
+            let bot = match seat {
+                Seat::Bot(bot) => bot,
+                Seat::User(_) => break,
+            };
+
+            let coords = match bot.choose_move(&entry.game) {
+                Some(coords) => coords,
+                None => break,
+            };
+            if let Err(err) = entry.game.make_move(coords) {
+                warn!(
+                    "Bot move {:?} rejected in game {}: {}",
+                    coords, game_id, err
+                );
+                break;
             }
+
+            debug!("Bot move {:?} in game {}", coords, game_id);
+            packets.extend(Self::move_packets(game_id, entry));
+        }
+
+        packets
+    }
+
+    // Board update and result of the last move for every user in the game
+    fn move_packets(game_id: &Uuid, entry: &ActiveGame) -> Vec<(UserId, Packet)> {
+        match entry.game.outcome() {
+            GameOutcome::InProgress => {}
+            GameOutcome::Win(win) => {
+                info!("Game won by {:?} ({:?}): {}", win.player, win.line, game_id);
+            }
+            outcome => info!("Game ended without winner ({:?}): {}", outcome, game_id),
+        }
+
+        let mut packets = Vec::new();
+        for event in Self::state_events(&entry.game) {
+            for user_id in entry.receivers() {
+                packets.push((*user_id, Packet::new(event.clone())));
+            }
+        }
+
+        packets
+    }
+
+    // Current board and the result once the game is over
+    fn state_events(game: &Game) -> Vec<Event> {
+        let mut events = vec![Event::State(*game.board(), game.current_player())];
+        match game.outcome() {
+            GameOutcome::InProgress => {}
+            GameOutcome::Win(win) => events.push(Event::Winner(win.player)),
+            _ => events.push(Event::Draw),
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::TOP_ROW_WIN;
+    use futures_channel::mpsc::{unbounded, UnboundedReceiver};
+    use tickoattwo::game::{FieldState, MoveError};
+    use tickoattwo::packet::NicknameError;
+    use tokio::time::advance;
+
+    fn join(backend: &mut Backend, port: u16) -> (UserId, UnboundedReceiver<Packet>) {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let (sender, mut receiver) = unbounded();
+        let user_id = backend.user_join(&addr, sender);
+
+        let packet = receiver.try_recv().unwrap();
+        assert!(matches!(packet.event, Event::Session(_)));
+        (user_id, receiver)
+    }
+
+    // Connect and wait for an opponent
+    fn join_queue(backend: &mut Backend, port: u16) -> (UserId, UnboundedReceiver<Packet>) {
+        let (user_id, receiver) = join(backend, port);
+        backend.dispatch_event(Event::JoinQueue, &user_id);
+        (user_id, receiver)
+    }
+
+    // Send the moves alternately from the two players, starting with the first
+    fn play_moves(backend: &mut Backend, players: [&UserId; 2], moves: &[(u8, u8)]) {
+        for (i, (row, col)) in moves.iter().enumerate() {
+            backend.dispatch_event(Event::Move(*row, *col), players[i % 2]);
+        }
+    }
+
+    fn received(receiver: &mut UnboundedReceiver<Packet>) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Ok(packet) = receiver.try_recv() {
+            events.push(packet.event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_start_notifies_both_players() {
+        let mut backend = Backend::new();
+
+        let (_, mut rx1) = join_queue(&mut backend, 1);
+        assert_eq!(received(&mut rx1), vec![]);
+
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+    }
+
+    // Test connections behind the same address are told apart
+    #[test]
+    fn test_users_share_address() {
+        let mut backend = Backend::new();
+        let (_, mut rx1) = join_queue(&mut backend, 1);
+        let (_, mut rx2) = join_queue(&mut backend, 1);
+
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+        assert_eq!(lock(&backend.users).len(), 2);
+    }
+
+    #[test]
+    fn test_move_pushed_to_opponent() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        backend.dispatch_event(Event::Move(1, 1), &user1);
+
+        let mut board = [[FieldState::None; 3]; 3];
+        board[1][1] = FieldState::OccupiedByOne(Player::Horizontal);
+        let state = Event::State(board, Player::Vertical);
+        assert_eq!(received(&mut rx1), vec![state.clone()]);
+        assert_eq!(received(&mut rx2), vec![state]);
+
+        // Out of turn moves are only answered to the sender
+        backend.dispatch_event(Event::Move(0, 0), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::InvalidMove(MoveError::NotYourTurn)]
+        );
+        assert_eq!(received(&mut rx2), vec![]);
+
+        backend.dispatch_event(Event::Move(1, 1), &user2);
+        assert_eq!(
+            received(&mut rx2),
+            vec![Event::InvalidMove(MoveError::RepeatedLastMove)]
+        );
+        assert_eq!(received(&mut rx1), vec![]);
+    }
+
+    #[test]
+    fn test_winner_is_player_completing_line() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+
+        play_moves(&mut backend, [&user1, &user2], &TOP_ROW_WIN);
+
+        for rx in [&mut rx1, &mut rx2] {
+            let events = received(rx);
+            assert_eq!(events.last(), Some(&Event::Winner(Player::Vertical)));
         }
 
-        None
+        backend.dispatch_event(Event::Move(2, 2), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::InvalidMove(MoveError::GameOver)]
+        );
+    }
+
+    #[test]
+    fn test_bot_game() {
+        let mut backend = Backend::new();
+        let (user_id, mut rx) = join_queue(&mut backend, 1);
+
+        backend.dispatch_event(Event::PlayBot(Difficulty::Medium), &user_id);
+        assert_eq!(received(&mut rx), vec![Event::Start(Player::Horizontal)]);
+
+        // Bot answers every move until the game is over
+        let mut game = Game::new();
+        while !game.is_finished() {
+            let coords = game.legal_moves().next().unwrap();
+            backend.dispatch_event(Event::Move(coords.0, coords.1), &user_id);
+
+            for event in received(&mut rx) {
+                if let Event::State(board, _) = event {
+                    assert_ne!(board, *game.board());
+                    for coords in game.legal_moves().collect::<Vec<_>>() {
+                        let mut next = game.clone();
+                        next.make_move(coords).unwrap();
+                        if next.board() == &board {
+                            game = next;
+                            break;
+                        }
+                    }
+                    assert_eq!(game.board(), &board);
+                }
+            }
+        }
+
+        // Queue is free for the next users
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx2), vec![]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_timeout_offers_bot() {
+        let mut backend = Backend::new();
+        let (user_id, mut rx) = join_queue(&mut backend, 1);
+
+        advance(Duration::from_secs(29)).await;
+        backend.check_queue_timeouts();
+        assert_eq!(received(&mut rx), vec![]);
+
+        advance(Duration::from_secs(1)).await;
+        backend.check_queue_timeouts();
+        assert_eq!(
+            received(&mut rx),
+            vec![Event::NoOpponent(Some(Difficulty::Medium))]
+        );
+
+        // Offer is only sent once and can be accepted
+        backend.check_queue_timeouts();
+        assert_eq!(received(&mut rx), vec![]);
+        backend.dispatch_event(Event::PlayBot(Difficulty::Medium), &user_id);
+        assert_eq!(received(&mut rx), vec![Event::Start(Player::Horizontal)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_timeout_notify() {
+        let mut backend = Backend::with_config(Config {
+            queue_timeout: Duration::from_secs(5),
+            queue_fallback: QueueFallback::Notify,
+            ..Config::default()
+        });
+        let (_, mut rx1) = join_queue(&mut backend, 1);
+
+        advance(Duration::from_secs(5)).await;
+        backend.check_queue_timeouts();
+        assert_eq!(received(&mut rx1), vec![Event::NoOpponent(None)]);
+
+        // Timed out user no longer blocks the queue
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        advance(Duration::from_secs(4)).await;
+        backend.check_queue_timeouts();
+        assert_eq!(received(&mut rx1), vec![]);
+        assert_eq!(received(&mut rx2), vec![]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_matched_user_does_not_time_out() {
+        let mut backend = Backend::new();
+        let (_, mut rx1) = join_queue(&mut backend, 1);
+
+        advance(Duration::from_secs(20)).await;
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+
+        advance(Duration::from_secs(20)).await;
+        backend.check_queue_timeouts();
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+    }
+
+    #[test]
+    fn test_leave_queue_without_disconnecting() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+
+        backend.dispatch_event(Event::LeaveQueue, &user1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx1), vec![]);
+        assert_eq!(received(&mut rx2), vec![]);
+
+        backend.dispatch_event(Event::JoinQueue, &user1);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Vertical)]);
+
+        // Already playing
+        backend.dispatch_event(Event::JoinQueue, &user2);
+        let (_, mut rx3) = join_queue(&mut backend, 3);
+        assert_eq!(received(&mut rx3), vec![]);
+    }
+
+    #[test]
+    fn test_requeue_after_game() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+
+        play_moves(&mut backend, [&user1, &user2], &TOP_ROW_WIN);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        backend.dispatch_event(Event::JoinQueue, &user2);
+        backend.dispatch_event(Event::JoinQueue, &user1);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Vertical)]);
+        assert_eq!(lock(&backend.games).len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requeue_after_opponent_left() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, _) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+
+        // Remaining player is told right away how long the opponent has to come back
+        backend.user_leave(&user2);
+        assert_eq!(received(&mut rx1), vec![Event::OpponentDisconnected(60)]);
+
+        advance(backend.config.reconnect_grace).await;
+        backend.check_reconnect_timeouts();
+        assert!(lock(&backend.users).contains_key(&user1));
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::OpponentLeft, Event::Winner(Player::Horizontal)]
+        );
+
+        // Remaining player is queued again right away
+        let (_, mut rx3) = join_queue(&mut backend, 3);
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx3), vec![Event::Start(Player::Vertical)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_both_players_disconnect() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, _) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+
+        backend.user_leave(&user2);
+        advance(Duration::from_secs(10)).await;
+        backend.user_leave(&user1);
+
+        // Player still disconnected is not queued when the opponent times out
+        advance(backend.config.reconnect_grace - Duration::from_secs(10)).await;
+        backend.check_reconnect_timeouts();
+        assert!(!lock(&backend.users).contains_key(&user2));
+        assert!(lock(&backend.users)[&user1].game.is_none());
+        assert!(!backend.queue.contains(&user1));
+
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        assert_eq!(received(&mut rx3), vec![]);
+
+        advance(Duration::from_secs(10)).await;
+        backend.check_reconnect_timeouts();
+        assert!(!lock(&backend.users).contains_key(&user1));
+        assert!(backend.queue.contains(&user3));
+        assert_eq!(backend.queue.len(), 1);
+    }
+
+    // Test a finished game is not awarded again when a player leaves
+    #[test]
+    fn test_leave_after_finished_game() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+
+        play_moves(&mut backend, [&user1, &user2], &TOP_ROW_WIN);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        backend.user_leave(&user2);
+        assert_eq!(received(&mut rx1), vec![]);
+        assert!(lock(&backend.games).is_empty());
+
+        // Nickname of the remaining player can still be changed
+        backend.dispatch_event(Event::Nickname(String::from("oat")), &user1);
+        assert_eq!(lock(&backend.users)[&user1].username, "oat");
+    }
+
+    fn create_room(
+        backend: &mut Backend,
+        user_id: &UserId,
+        rx: &mut UnboundedReceiver<Packet>,
+    ) -> String {
+        backend.dispatch_event(Event::CreateRoom, user_id);
+        match received(rx)[..] {
+            [Event::RoomCreated(ref code)] => code.clone(),
+            ref events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_room_starts_game_with_friend() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let code = create_room(&mut backend, &user1, &mut rx1);
+
+        // Room members are not paired through the queue
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx2), vec![]);
+
+        backend.dispatch_event(Event::JoinRoom(code.to_lowercase()), &user2);
+        assert_eq!(received(&mut rx1), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+        assert!(!backend.queue.contains(&user2));
+
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        backend.dispatch_event(Event::JoinRoom(code), &user3);
+        assert_eq!(
+            received(&mut rx3),
+            vec![Event::RoomError(RoomError::NotFound)]
+        );
+    }
+
+    #[test]
+    fn test_room_unknown_code() {
+        let mut backend = Backend::new();
+        let (user_id, mut rx) = join_queue(&mut backend, 1);
+
+        backend.dispatch_event(Event::JoinRoom(String::from("NOROOM")), &user_id);
+        assert_eq!(
+            received(&mut rx),
+            vec![Event::RoomError(RoomError::NotFound)]
+        );
+
+        // Failed join leaves the user in the queue
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+    }
+
+    #[test]
+    fn test_room_expires_when_empty() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+
+        let code1 = create_room(&mut backend, &user1, &mut rx1);
+        backend.dispatch_event(Event::LeaveRoom, &user1);
+        assert!(lock(&backend.rooms).is_empty());
+
+        let code2 = create_room(&mut backend, &user1, &mut rx1);
+        backend.user_leave(&user1);
+        assert!(lock(&backend.rooms).is_empty());
+
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        for code in [code1, code2] {
+            backend.dispatch_event(Event::JoinRoom(code), &user2);
+            assert_eq!(
+                received(&mut rx2),
+                vec![Event::RoomError(RoomError::NotFound)]
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_room_removed_once_game_starts() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let code = create_room(&mut backend, &user1, &mut rx1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        backend.dispatch_event(Event::JoinRoom(code.clone()), &user2);
+        assert!(lock(&backend.rooms).is_empty());
+        let game_id = lock(&backend.users)[&user2].game;
+
+        // Leaving or dropping out of the game doesn't open the room to others
+        backend.dispatch_event(Event::LeaveRoom, &user1);
+        backend.user_leave(&user1);
+        let (user3, mut rx3) = join(&mut backend, 3);
+        backend.dispatch_event(Event::JoinRoom(code), &user3);
+        assert_eq!(
+            received(&mut rx3),
+            vec![Event::RoomError(RoomError::NotFound)]
+        );
+        assert_eq!(lock(&backend.games).len(), 1);
+        assert_eq!(lock(&backend.users)[&user2].game, game_id);
+
+        // Remaining member goes back to the queue once the opponent is gone for good
+        received(&mut rx2);
+        advance(backend.config.reconnect_grace).await;
+        backend.check_reconnect_timeouts();
+        assert!(backend.queue.contains(&user2));
+        assert_eq!(
+            received(&mut rx2),
+            vec![Event::OpponentLeft, Event::Winner(Player::Vertical)]
+        );
+    }
+
+    #[test]
+    fn test_spectator_follows_game() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        backend.dispatch_event(Event::Move(1, 1), &user1);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        let game_id = *lock(&backend.games).keys().next().unwrap();
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        backend.dispatch_event(Event::Spectate(game_id), &user3);
+
+        let mut board = [[FieldState::None; 3]; 3];
+        board[1][1] = FieldState::OccupiedByOne(Player::Horizontal);
+        assert_eq!(
+            received(&mut rx3),
+            vec![
+                Event::State(board, Player::Vertical),
+                Event::SpectatorCount(1)
+            ]
+        );
+        assert_eq!(received(&mut rx1), vec![Event::SpectatorCount(1)]);
+        assert_eq!(received(&mut rx2), vec![Event::SpectatorCount(1)]);
+
+        // Spectators are read-only
+        backend.dispatch_event(Event::Move(0, 0), &user3);
+        assert_eq!(
+            received(&mut rx3),
+            vec![Event::Error(ServerError::Spectating)]
+        );
+        assert_eq!(received(&mut rx2), vec![]);
+
+        backend.user_leave(&user3);
+        assert_eq!(received(&mut rx1), vec![Event::SpectatorCount(0)]);
+        assert_eq!(received(&mut rx2), vec![Event::SpectatorCount(0)]);
+    }
+
+    #[test]
+    fn test_spectator_receives_moves() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        let game_id = *lock(&backend.games).keys().next().unwrap();
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        backend.dispatch_event(Event::Spectate(game_id), &user3);
+        received(&mut rx2);
+        received(&mut rx3);
+
+        backend.dispatch_event(Event::Move(2, 2), &user1);
+        assert_eq!(received(&mut rx3), received(&mut rx2));
+
+        backend.dispatch_event(Event::StopSpectating, &user3);
+        backend.dispatch_event(Event::Move(2, 1), &user1);
+        assert_eq!(received(&mut rx3), vec![]);
+        assert!(!received(&mut rx2).is_empty());
+    }
+
+    #[test]
+    fn test_spectate_unknown_game() {
+        let mut backend = Backend::new();
+        let (user_id, mut rx) = join_queue(&mut backend, 1);
+
+        backend.dispatch_event(Event::Spectate(Uuid::new_v4()), &user_id);
+        assert_eq!(received(&mut rx), vec![Event::GameNotFound]);
+
+        // Moves without a game are ignored rather than treated as spectating
+        backend.dispatch_event(Event::Move(0, 0), &user_id);
+        assert_eq!(received(&mut rx), vec![]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spectator_detached_when_game_removed() {
+        let mut backend = Backend::new();
+        let (_, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, _) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+
+        let game_id = *lock(&backend.games).keys().next().unwrap();
+        let (user3, mut rx3) = join(&mut backend, 3);
+        backend.dispatch_event(Event::Spectate(game_id), &user3);
+        received(&mut rx3);
+
+        backend.user_leave(&user2);
+        advance(backend.config.reconnect_grace).await;
+        backend.check_reconnect_timeouts();
+        assert_eq!(
+            received(&mut rx3),
+            vec![
+                Event::OpponentDisconnected(60),
+                Event::Winner(Player::Horizontal),
+                Event::GameNotFound
+            ]
+        );
+        assert_eq!(lock(&backend.users)[&user3].spectating, None);
+    }
+
+    #[test]
+    fn test_nickname_reply() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        backend.dispatch_event(Event::Nickname(String::from("  Tick  Oat ")), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::NicknameAccepted(String::from("Tick Oat"))]
+        );
+
+        for (nickname, err) in [
+            ("tick oat", NicknameError::Taken),
+            ("", NicknameError::TooShort),
+            ("tick\noat\u{7}", NicknameError::InvalidCharacter),
+        ] {
+            backend.dispatch_event(Event::Nickname(String::from(nickname)), &user2);
+            assert_eq!(received(&mut rx2), vec![Event::NicknameRejected(err)]);
+        }
+        assert_eq!(lock(&backend.users)[&user2].username, "");
+
+        // Own nickname can be set again in another case
+        backend.dispatch_event(Event::Nickname(String::from("tick oat")), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::NicknameAccepted(String::from("tick oat"))]
+        );
+    }
+
+    fn lobby(received: Vec<Event>) -> Lobby {
+        match &received[..] {
+            [Event::Lobby(lobby)] => lobby.clone(),
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_lobby_lists_running_games() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        backend.dispatch_event(Event::Nickname(String::from("tick")), &user1);
+        received(&mut rx1);
+        backend.dispatch_event(Event::SubscribeLobby, &user1);
+        assert_eq!(
+            lobby(received(&mut rx1)),
+            Lobby {
+                games: Vec::new(),
+                queued: 1,
+                online: 1,
+            }
+        );
+
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        backend.dispatch_event(Event::Nickname(String::from("oat")), &user2);
+        backend.dispatch_event(Event::Move(1, 1), &user1);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        backend.dispatch_event(Event::SubscribeLobby, &user3);
+        let game_id = *lock(&backend.games).keys().next().unwrap();
+        assert_eq!(
+            lobby(received(&mut rx3)),
+            Lobby {
+                games: vec![LobbyGame {
+                    id: game_id,
+                    horizontal: String::from("tick"),
+                    vertical: String::from("oat"),
+                    moves: 1,
+                    spectators: 0,
+                }],
+                queued: 1,
+                online: 3,
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lobby_pushes_changes() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join(&mut backend, 1);
+        backend.dispatch_event(Event::SubscribeLobby, &user1);
+        received(&mut rx1);
+
+        let (user2, _) = join(&mut backend, 2);
+        assert_eq!(lobby(received(&mut rx1)).online, 2);
+        backend.dispatch_event(Event::JoinQueue, &user2);
+        assert_eq!(lobby(received(&mut rx1)).queued, 1);
+
+        // Unchanged lobby is not pushed again
+        backend.check_queue_timeouts();
+        backend.dispatch_event(Event::JoinQueue, &user2);
+        assert_eq!(received(&mut rx1), vec![]);
+
+        backend.dispatch_event(Event::PlayBot(Difficulty::Easy), &user2);
+        let games = lobby(received(&mut rx1)).games;
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].vertical, "Bot");
+
+        backend.dispatch_event(Event::Spectate(games[0].id), &user1);
+        let events = received(&mut rx1);
+        assert!(events.contains(&Event::SpectatorCount(1)));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Lobby(lobby) if lobby.games[0].spectators == 1)));
+
+        backend.dispatch_event(Event::UnsubscribeLobby, &user1);
+        backend.user_leave(&user2);
+        advance(backend.config.reconnect_grace).await;
+        backend.check_reconnect_timeouts();
+        assert_eq!(
+            received(&mut rx1),
+            vec![
+                Event::OpponentDisconnected(60),
+                Event::Winner(Player::Vertical),
+                Event::GameNotFound
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_after_disconnect() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        backend.dispatch_event(Event::Move(1, 1), &user1);
+        received(&mut rx1);
+        received(&mut rx2);
+
+        let session = lock(&backend.users)[&user2].session;
+        backend.user_leave(&user2);
+
+        // Game goes on while the player is away
+        advance(backend.config.reconnect_grace / 2).await;
+        backend.check_reconnect_timeouts();
+        backend.dispatch_event(Event::Move(0, 0), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![
+                Event::OpponentDisconnected(60),
+                Event::InvalidMove(MoveError::NotYourTurn)
+            ]
+        );
+
+        let (user3, mut rx3) = join(&mut backend, 3);
+        backend.dispatch_event(Event::Resume(session), &user3);
+
+        let mut board = [[FieldState::None; 3]; 3];
+        board[1][1] = FieldState::OccupiedByOne(Player::Horizontal);
+        assert_eq!(
+            received(&mut rx3),
+            vec![
+                Event::Session(session),
+                Event::Start(Player::Vertical),
+                Event::State(board, Player::Vertical),
+                Event::SpectatorCount(0),
+            ]
+        );
+
+        assert_eq!(received(&mut rx1), vec![Event::OpponentReconnected]);
+
+        backend.dispatch_event(Event::Move(0, 0), &user3);
+        assert_eq!(received(&mut rx1).len(), 1);
+        assert!(!lock(&backend.users).contains_key(&user2));
+
+        // Session can only be resumed while disconnected
+        let (user4, mut rx4) = join(&mut backend, 4);
+        backend.dispatch_event(Event::Resume(session), &user4);
+        assert_eq!(received(&mut rx4), vec![Event::ResumeFailed]);
+    }
+
+    // Test a reconnecting player is not paired with a waiting user before resuming
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_while_others_queued() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, _) = join_queue(&mut backend, 2);
+        let (user3, mut rx3) = join_queue(&mut backend, 3);
+        received(&mut rx1);
+
+        let session = lock(&backend.users)[&user2].session;
+        backend.user_leave(&user2);
+
+        let (user4, mut rx4) = join(&mut backend, 4);
+        assert_eq!(received(&mut rx3), vec![]);
+        backend.dispatch_event(Event::Resume(session), &user4);
+
+        let events = received(&mut rx4);
+        assert_eq!(
+            events[..2],
+            [Event::Session(session), Event::Start(Player::Vertical)]
+        );
+        assert!(backend.queue.contains(&user3));
+        assert_eq!(lock(&backend.games).len(), 1);
+
+        // Resuming a session while in a game of its own is refused
+        let (user5, mut rx5) = join_queue(&mut backend, 5);
+        assert_eq!(received(&mut rx5), vec![Event::Start(Player::Vertical)]);
+        let session = lock(&backend.users)[&user1].session;
+        backend.user_leave(&user1);
+        backend.dispatch_event(Event::Resume(session), &user5);
+        assert_eq!(received(&mut rx5), vec![Event::ResumeFailed]);
+        assert!(lock(&backend.users)[&user1].disconnected_at.is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_after_grace_period_fails() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        let (user2, _) = join_queue(&mut backend, 2);
+        received(&mut rx1);
+
+        let session = lock(&backend.users)[&user2].session;
+        backend.user_leave(&user2);
+        advance(backend.config.reconnect_grace).await;
+        backend.check_reconnect_timeouts();
+        assert!(lock(&backend.games).is_empty());
+        assert_eq!(lock(&backend.users)[&user1].game, None);
+
+        let (user3, mut rx3) = join(&mut backend, 3);
+        backend.dispatch_event(Event::Resume(session), &user3);
+        assert_eq!(received(&mut rx3), vec![Event::ResumeFailed]);
+    }
+
+    #[test]
+    fn test_event_after_leave() {
+        let mut backend = Backend::new();
+        let (user1, _) = join_queue(&mut backend, 1);
+        backend.user_leave(&user1);
+
+        for event in [
+            Event::Nickname(String::from("oat")),
+            Event::JoinQueue,
+            Event::Move(0, 0),
+            Event::CreateRoom,
+        ] {
+            assert_eq!(
+                backend.handle_event(event, &user1),
+                Err(BackendError::UnknownUser(user1))
+            );
+        }
+
+        // Backend keeps working for everyone else
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        let (_, mut rx3) = join_queue(&mut backend, 3);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx3), vec![Event::Start(Player::Vertical)]);
+    }
+
+    #[test]
+    fn test_move_in_removed_game() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+        join_queue(&mut backend, 2);
+        received(&mut rx1);
+
+        let game_id = *lock(&backend.games).keys().next().unwrap();
+        lock(&backend.games).remove(&game_id);
+
+        assert_eq!(
+            backend.handle_event(Event::Move(0, 0), &user1),
+            Err(BackendError::UnknownGame(game_id))
+        );
+
+        // Client is told about the error
+        backend.dispatch_event(Event::Move(0, 0), &user1);
+        assert_eq!(
+            received(&mut rx1),
+            vec![Event::Error(ServerError::UnknownGame)]
+        );
+    }
+
+    #[test]
+    fn test_queued_user_left_before_pairing() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+
+        // Queue still holds a user the store no longer knows
+        lock(&backend.users).remove(&user1);
+        let (user2, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(received(&mut rx1), vec![]);
+        assert_eq!(received(&mut rx2), vec![]);
+        assert!(backend.queue.contains(&user2));
+
+        let (_, mut rx3) = join_queue(&mut backend, 3);
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Horizontal)]);
+        assert_eq!(received(&mut rx3), vec![Event::Start(Player::Vertical)]);
+    }
+
+    #[test]
+    fn test_recovers_poisoned_lock() {
+        let mut backend = Backend::new();
+        let (user1, mut rx1) = join_queue(&mut backend, 1);
+
+        let users = backend.users.clone();
+        let result = std::thread::spawn(move || {
+            let _users = users.lock().unwrap();
+            panic!("poisoning the user store");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(backend.users.is_poisoned());
+
+        backend.dispatch_event(Event::Nickname(String::from("oat")), &user1);
+        let (_, mut rx2) = join_queue(&mut backend, 2);
+        assert_eq!(
+            received(&mut rx1),
+            vec![
+                Event::NicknameAccepted(String::from("oat")),
+                Event::Start(Player::Horizontal)
+            ]
+        );
+        assert_eq!(received(&mut rx2), vec![Event::Start(Player::Vertical)]);
+        assert!(!backend.users.is_poisoned());
+    }
+
+    // Test many users joining and leaving are all paired exactly once
+    #[test]
+    fn test_many_joins_and_leaves() {
+        let mut backend = Backend::new();
+        let mut connected = Vec::new();
+
+        for port in 1..=200 {
+            connected.push(join_queue(&mut backend, port));
+
+            // Every third user gives up right away
+            if port % 3 == 0 {
+                let (user_id, _) = connected.pop().unwrap();
+                backend.user_leave(&user_id);
+            }
+        }
+
+        let mut starts = Vec::new();
+        for (user_id, rx) in connected.iter_mut() {
+            match received(rx)[..] {
+                [Event::Start(player)] => starts.push((*user_id, player)),
+                [] => {}
+                ref events => panic!("unexpected events: {:?}", events),
+            }
+        }
+
+        assert_eq!(connected.len() - starts.len(), backend.queue.len());
+        assert!(backend.queue.len() <= 1);
+        assert_eq!(lock(&backend.games).len() * 2, starts.len());
+        assert_eq!(
+            starts
+                .iter()
+                .filter(|(_, p)| *p == Player::Horizontal)
+                .count(),
+            starts.len() / 2
+        );
+    }
+
+    // Test joins and leaves from concurrent tasks keep the queue and games consistent
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_joins_and_leaves() {
+        let backend = Arc::new(Mutex::new(Backend::new()));
+
+        let tasks = (0..8u16).map(|task| {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                let mut connected = Vec::new();
+                for i in 0..50u16 {
+                    let (user_id, rx) = join_queue(&mut lock(&backend), task * 100 + i);
+                    lock(&backend).dispatch_event(Event::JoinQueue, &user_id);
+                    tokio::task::yield_now().await;
+
+                    match i % 4 {
+                        // Gives up waiting but stays connected
+                        1 => lock(&backend).dispatch_event(Event::LeaveQueue, &user_id),
+                        // Disconnects, possibly from a running game
+                        2 => lock(&backend).user_leave(&user_id),
+                        _ => {}
+                    }
+                    connected.push((user_id, rx));
+                    tokio::task::yield_now().await;
+                }
+                connected
+            })
+        });
+
+        let mut connected = Vec::new();
+        for task in tasks.collect::<Vec<_>>() {
+            connected.extend(task.await.unwrap());
+        }
+
+        let backend = lock(&backend);
+        let users = lock(&backend.users);
+        let games = lock(&backend.games);
+
+        // Nobody waits while another could be paired with them
+        assert!(backend.queue.len() <= 1);
+        for (user_id, user) in users.iter() {
+            if backend.queue.contains(user_id) {
+                assert_eq!(user.game, None);
+                assert!(user.disconnected_at.is_none());
+            }
+            if let Some(game_id) = user.game {
+                assert!(games[&game_id].players().any(|player| player == user_id));
+            }
+        }
+
+        // Every game was announced to exactly its two players
+        let mut starts = 0;
+        for (_, rx) in connected.iter_mut() {
+            let events = received(rx);
+            starts += events
+                .iter()
+                .filter(|event| matches!(event, Event::Start(_)))
+                .count();
+        }
+        assert_eq!(starts, games.len() * 2);
+        for entry in games.values() {
+            assert_eq!(entry.players().count(), 2);
+        }
     }
 }
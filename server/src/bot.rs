@@ -0,0 +1,150 @@
+use rand::seq::SliceRandom;
+use std::sync::OnceLock;
+
+use tickoattwo::game::{Game, GameOutcome};
+use tickoattwo::packet::Difficulty;
+use tickoattwo::solver::Solver;
+
+// Search depth of the minimax bot, in moves
+const MINIMAX_DEPTH: u8 = 4;
+
+// Solved once and shared by all perfect bots
+static SOLVER: OnceLock<Solver> = OnceLock::new();
+
+// Solve the game for the perfect bots, takes a few seconds the first time
+pub fn solver() -> &'static Solver {
+    SOLVER.get_or_init(Solver::solved)
+}
+
+pub enum Bot {
+    // Plays any legal move
+    Random,
+    // Wins when it can and avoids handing the opponent a win
+    Greedy,
+    // Looks ahead a fixed number of moves
+    Minimax(u8),
+    // Never misses a forced win
+    Perfect,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty) -> Self {
+        match difficulty {
+            Difficulty::Easy => Bot::Random,
+            Difficulty::Medium => Bot::Greedy,
+            Difficulty::Hard => Bot::Minimax(MINIMAX_DEPTH),
+            Difficulty::Perfect => Bot::Perfect,
+        }
+    }
+
+    pub fn choose_move(&self, game: &Game) -> Option<(u8, u8)> {
+        let mut rng = rand::thread_rng();
+
+        match self {
+            Bot::Random => game
+                .legal_moves()
+                .collect::<Vec<_>>()
+                .choose(&mut rng)
+                .copied(),
+            Bot::Greedy => Self::best_moves(game, 2).choose(&mut rng).copied(),
+            Bot::Minimax(depth) => Self::best_moves(game, *depth).choose(&mut rng).copied(),
+            Bot::Perfect => solver().known_best_move(game),
+        }
+    }
+
+    // Moves with the highest minimax score when looking ahead the given number of moves
+    fn best_moves(game: &Game, depth: u8) -> Vec<(u8, u8)> {
+        let mut game = game.clone();
+        let moves = game.legal_moves().collect::<Vec<_>>();
+
+        let mut best_score = i32::MIN;
+        let mut best_moves = Vec::new();
+
+        for coords in moves {
+            game.make_move(coords).unwrap();
+            let score = -Self::negamax(&mut game, depth.saturating_sub(1));
+            game.undo();
+
+            if score > best_score {
+                best_score = score;
+                best_moves.clear();
+            }
+            if score == best_score {
+                best_moves.push(coords);
+            }
+        }
+
+        best_moves
+    }
+
+    // Score for the player to move, preferring faster wins and slower losses
+    fn negamax(game: &mut Game, depth: u8) -> i32 {
+        match game.outcome() {
+            GameOutcome::Win(_) => return -(100 + depth as i32),
+            GameOutcome::Draw | GameOutcome::Stalemate(_) => return 0,
+            GameOutcome::InProgress => {}
+        }
+
+        if depth == 0 {
+            return 0;
+        }
+
+        let moves = game.legal_moves().collect::<Vec<_>>();
+        let mut best_score = i32::MIN;
+
+        for coords in moves {
+            game.make_move(coords).unwrap();
+            best_score = best_score.max(-Self::negamax(game, depth - 1));
+            game.undo();
+        }
+
+        best_score
+    }
+}
+
+// Moves in which vertical completes the top row
+#[cfg(test)]
+pub(crate) const TOP_ROW_WIN: [(u8, u8); 6] = [(0, 0), (0, 1), (0, 2), (0, 0), (0, 1), (0, 2)];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(moves: &[(u8, u8)]) -> Game {
+        let mut game = Game::new();
+        for coords in moves {
+            game.make_move(*coords).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_bots_play_legal_moves() {
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let bot = Bot::new(difficulty);
+            let mut game = Game::new();
+
+            while let Some(coords) = bot.choose_move(&game) {
+                assert_eq!(game.make_move(coords), Ok(()));
+            }
+            assert!(game.is_finished());
+        }
+    }
+
+    // Test bots take a winning move and block the opponent's
+    #[test]
+    fn test_bots_see_wins() {
+        // Vertical completes the top row with (0, 2)
+        let winning = play(&TOP_ROW_WIN[..5]);
+        // Vertical must not play (0, 1), which lets horizontal complete the top row
+        let losing = play(&[(0, 0), (0, 2), (0, 1), (0, 0), (1, 1)]);
+
+        for bot in [Bot::Greedy, Bot::Minimax(MINIMAX_DEPTH), Bot::Perfect] {
+            assert_eq!(bot.choose_move(&winning), Some((0, 2)));
+
+            for _ in 0..10 {
+                assert_ne!(bot.choose_move(&losing), Some((0, 1)));
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::{error::Error, fmt};
+
+use tickoattwo::packet::{
+    DecodeError, Event, Handshake, BINARY_FEATURE, JSON_FEATURE, MSGPACK_FEATURE, PROTOCOL_VERSION,
+};
+
+// Oldest protocol version of clients still understood
+const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// Optional parts of the protocol offered to clients, only the frame encodings
+// change how the server talks to a client
+const FEATURES: &[&str] = &[BINARY_FEATURE, JSON_FEATURE, MSGPACK_FEATURE];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    // First packet of the client could not be decoded
+    InvalidPacket(DecodeError),
+    // Client did not start with a hello
+    MissingHello,
+    UnsupportedVersion(u32),
+}
+
+impl HandshakeError {
+    // Reason sent in the close frame, which is limited to 123 bytes, so the
+    // decode error with the client's own input only goes to the log
+    pub fn close_reason(&self) -> String {
+        match self {
+            HandshakeError::InvalidPacket(_) => String::from("Invalid hello"),
+            err => err.to_string(),
+        }
+    }
+}
+
+impl From<DecodeError> for HandshakeError {
+    fn from(err: DecodeError) -> Self {
+        HandshakeError::InvalidPacket(err)
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandshakeError::InvalidPacket(err) => write!(f, "Invalid hello: {}", err),
+            HandshakeError::MissingHello => write!(f, "Expected hello as first packet"),
+            HandshakeError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported protocol version {}, server supports {} to {}",
+                version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for HandshakeError {}
+
+// Answer the first packet of a client with the version and features both sides support
+pub fn accept(event: Event) -> Result<Handshake, HandshakeError> {
+    let hello = match event {
+        Event::Hello(hello) => hello,
+        _ => return Err(HandshakeError::MissingHello),
+    };
+
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&hello.version) {
+        return Err(HandshakeError::UnsupportedVersion(hello.version));
+    }
+
+    let features = FEATURES
+        .iter()
+        .filter(|feature| hello.features.iter().any(|wanted| wanted == *feature))
+        .map(|feature| feature.to_string())
+        .collect();
+
+    Ok(Handshake {
+        version: hello.version,
+        features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(version: u32, features: &[&str]) -> Event {
+        Event::Hello(Handshake {
+            version,
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_accept_current_version() {
+        assert_eq!(
+            accept(hello(
+                PROTOCOL_VERSION,
+                &["msgpack", "teleport", "rooms", "binary"]
+            )),
+            Ok(Handshake {
+                version: PROTOCOL_VERSION,
+                features: vec![String::from("binary"), String::from("msgpack")],
+            })
+        );
+        assert_eq!(
+            accept(hello(PROTOCOL_VERSION, &[])).unwrap().features,
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_reject_mismatched_versions() {
+        for version in [MIN_PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1, u32::MAX] {
+            assert_eq!(
+                accept(hello(version, &[])),
+                Err(HandshakeError::UnsupportedVersion(version))
+            );
+        }
+    }
+
+    #[test]
+    fn test_close_reason_is_short() {
+        let err = HandshakeError::from(DecodeError::UnknownEvent("x".repeat(300)));
+        assert_eq!(err.close_reason(), "Invalid hello");
+
+        for err in [
+            HandshakeError::MissingHello,
+            HandshakeError::UnsupportedVersion(u32::MAX),
+        ] {
+            assert!(err.close_reason().len() <= 123);
+        }
+    }
+
+    #[test]
+    fn test_reject_missing_hello() {
+        assert_eq!(
+            accept(Event::Nickname(String::from("oat"))),
+            Err(HandshakeError::MissingHello)
+        );
+        assert_eq!(
+            accept(Event::Welcome(Handshake {
+                version: PROTOCOL_VERSION,
+                features: Vec::new(),
+            })),
+            Err(HandshakeError::MissingHello)
+        );
+    }
+}
@@ -1,4 +1,8 @@
 pub mod backend;
-pub mod game;
+pub mod bot;
+pub mod handshake;
 pub mod network;
+pub mod nickname;
+pub mod queue;
+pub mod room;
 pub mod user;
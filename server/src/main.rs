@@ -1,7 +1,9 @@
 use std::env;
 
+use log::info;
 use simple_logger::SimpleLogger;
 use tickoattwo_server::backend::Backend;
+use tickoattwo_server::bot;
 use tickoattwo_server::network::serve;
 
 #[tokio::main]
@@ -17,6 +19,10 @@ async fn main() -> Result<(), hyper::Error> {
         .nth(1)
         .unwrap_or_else(|| "127.0.0.1:9000".to_string());
 
+    // Solve before accepting connections so perfect bot games never wait for it
+    info!("Solving the game for the perfect bot");
+    bot::solver();
+
     let backend = Backend::new();
 
     serve(backend, addr).await?;
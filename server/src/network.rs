@@ -1,4 +1,5 @@
 use futures::SinkExt;
+use futures_channel::mpsc::unbounded;
 use futures_util::StreamExt;
 
 use hyper::{
@@ -6,68 +7,129 @@ use hyper::{
     http::HeaderValue,
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
-    upgrade::Upgraded,
     Body, Method, Request, Response, Server, StatusCode, Version,
 };
 use log::{info, warn};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_tungstenite::WebSocketStream;
-use tungstenite::{handshake::derive_accept_key, protocol::Role, Error, Result};
+use tungstenite::{
+    handshake::derive_accept_key,
+    protocol::{frame::coding::CloseCode, CloseFrame, Role},
+    Error, Message, Result,
+};
 
 use std::{
     convert::Infallible,
     net::SocketAddr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use crate::{backend::Backend};
-use tickoattwo::packet::Packet;
+use crate::backend::{lock, Backend};
+use crate::handshake::{self, HandshakeError};
+use tickoattwo::packet::{Encoding, Event, Packet};
+
+// How long a client may take to say hello before it is disconnected
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
 
-async fn handle_connection(
+async fn handle_connection<S>(
     backend: Arc<Mutex<Backend>>,
-    ws_stream: WebSocketStream<Upgraded>,
+    ws_stream: WebSocketStream<S>,
     addr: SocketAddr,
-) -> Result<()> {
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     info!("WS: Connected: {}", addr);
 
     let (mut tx, mut rx) = ws_stream.split();
 
-    backend.lock().unwrap().user_join(&addr);
-
-    loop {
-        tokio::select! {
-            msg = rx.next() => {
-                match msg {
-                    Some(msg) => {
-                        let msg = msg?;
-                        if msg.is_text() {
-                            match Packet::decode_message(&msg) {
-                                Ok(packet) =>  {
-                                    let event = {
-                                        backend.lock().unwrap().dispatch_event(packet.event, &addr)
-                                    };
-                                    if let Some(res) = event {
-                                        tx.send(res.encode_message()).await?;
+    // Client has to introduce itself before anything else, in either frame type
+    let hello = match tokio::time::timeout(HELLO_TIMEOUT, rx.next()).await {
+        Ok(Some(msg)) => Packet::decode_message(&msg?, Encoding::Text),
+        Ok(None) => return Ok(()),
+        Err(_) => {
+            warn!("WS: No hello in time: {}", addr);
+            let frame = CloseFrame {
+                code: CloseCode::Policy,
+                reason: "Expected hello".into(),
+            };
+            tx.send(Message::Close(Some(frame))).await?;
+            return Ok(());
+        }
+    };
+    let encoding = match hello
+        .map_err(HandshakeError::from)
+        .and_then(|packet| handshake::accept(packet.event))
+    {
+        Ok(welcome) => {
+            info!(
+                "WS: Protocol {} ({:?}): {}",
+                welcome.version, welcome.features, addr
+            );
+            // Welcome is always text, the client learns about other encodings from it
+            let encoding = Encoding::negotiated(&welcome);
+            tx.send(Packet::new(Event::Welcome(welcome)).encode_message(Encoding::Text))
+                .await?;
+            encoding
+        }
+        Err(err) => {
+            warn!("WS: Handshake failed: {} ({})", err, addr);
+            let frame = CloseFrame {
+                code: CloseCode::Protocol,
+                reason: err.close_reason().into(),
+            };
+            tx.send(Message::Close(Some(frame))).await?;
+            return Ok(());
+        }
+    };
+
+    let (outbox_tx, mut outbox_rx) = unbounded();
+
+    let user_id = lock(&backend).user_join(&addr, outbox_tx);
+
+    // Errors end the connection like a close, the user is cleaned up either way
+    let result: Result<()> = async {
+        loop {
+            tokio::select! {
+                packet = outbox_rx.next() => {
+                    match packet {
+                        Some(packet) => tx.send(packet.encode_message(encoding)).await?,
+                        None => break,
+                    }
+                }
+                msg = rx.next() => {
+                    match msg {
+                        Some(msg) => {
+                            let msg = msg?;
+                            if msg.is_text() || msg.is_binary() {
+                                match Packet::decode_message(&msg, encoding) {
+                                    Ok(packet) =>  {
+                                        lock(&backend).dispatch_event(packet.event, &user_id);
+                                    },
+                                    Err(err) => {
+                                        warn!("Decode error: {}", err)
                                     }
-                                },
-                                Err(err) => {
-                                    warn!("Decode error: {}", err)
                                 }
+                            } else if msg.is_close() {
+                                break;
                             }
-                        } else if msg.is_close() {
-                            break;
                         }
+                        _ => break,
                     }
-                    _ => break,
                 }
             }
         }
+
+        Ok(())
     }
+    .await;
 
-    info!("WS: Disconnected: {}", &addr);
+    info!("WS: Disconnected: {} ({})", &addr, user_id);
 
-    backend.lock().unwrap().user_leave(&addr);
+    lock(&backend).user_leave(&user_id);
 
-    Ok(())
+    result
 }
 
 async fn handle_request(
@@ -86,7 +148,7 @@ async fn handle_request(
             .get(CONNECTION)
             .and_then(|h| h.to_str().ok())
             .map(|h| {
-                h.split(|c| c == ' ' || c == ',')
+                h.split([' ', ','])
                     .any(|p| p.eq_ignore_ascii_case("upgrade"))
             })
             .unwrap_or(false)
@@ -142,6 +204,18 @@ async fn handle_request(
 pub async fn serve(backend: Backend, addr: String) -> Result<(), hyper::Error> {
     let backend = Arc::new(Mutex::new(backend));
 
+    // Periodic housekeeping, like timing out queued and disconnected users
+    let ticker_backend = backend.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let mut backend = lock(&ticker_backend);
+            backend.check_queue_timeouts();
+            backend.check_reconnect_timeouts();
+        }
+    });
+
     let make_svc = make_service_fn(move |conn: &AddrStream| {
         let remote_addr = conn.remote_addr();
         let backend = backend.clone();
@@ -157,3 +231,97 @@ pub async fn serve(backend: Backend, addr: String) -> Result<(), hyper::Error> {
 
     Ok::<_, hyper::Error>(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tickoattwo::packet::{Handshake, PROTOCOL_VERSION};
+    use tokio::io::DuplexStream;
+
+    // Run a connection against an in-memory socket and return the client end of it
+    async fn connect() -> WebSocketStream<DuplexStream> {
+        let (client, server) = tokio::io::duplex(4096);
+        let backend = Arc::new(Mutex::new(Backend::new()));
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1));
+        tokio::spawn(async move {
+            let ws_stream = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+            handle_connection(backend, ws_stream, addr).await
+        });
+        WebSocketStream::from_raw_socket(client, Role::Client, None).await
+    }
+
+    async fn hello(client: &mut WebSocketStream<DuplexStream>, version: u32) {
+        let hello = Packet::new(Event::Hello(Handshake {
+            version,
+            features: Vec::new(),
+        }));
+        client
+            .send(hello.encode_message(Encoding::Text))
+            .await
+            .unwrap();
+    }
+
+    async fn close_code(client: &mut WebSocketStream<DuplexStream>) -> Option<CloseCode> {
+        match client.next().await {
+            Some(Ok(Message::Close(frame))) => frame.map(|frame| frame.code),
+            msg => panic!("Expected close, got {:?}", msg),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_welcome() {
+        let mut client = connect().await;
+        hello(&mut client, PROTOCOL_VERSION).await;
+
+        let msg = client.next().await.unwrap().unwrap();
+        assert_eq!(
+            Packet::decode_message(&msg, Encoding::Text).unwrap().event,
+            Event::Welcome(Handshake {
+                version: PROTOCOL_VERSION,
+                features: Vec::new(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_on_unsupported_version() {
+        let mut client = connect().await;
+        hello(&mut client, PROTOCOL_VERSION + 1).await;
+
+        assert_eq!(close_code(&mut client).await, Some(CloseCode::Protocol));
+    }
+
+    #[tokio::test]
+    async fn test_close_on_long_event_name() {
+        let mut client = connect().await;
+        let raw = format!("{}:", "x".repeat(300));
+        client.send(Message::Text(raw)).await.unwrap();
+
+        match client.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => {
+                assert_eq!(frame.code, CloseCode::Protocol);
+                assert_eq!(frame.reason, "Invalid hello");
+            }
+            msg => panic!("Expected close, got {:?}", msg),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_on_missing_hello() {
+        let mut client = connect().await;
+        client
+            .send(Packet::new(Event::JoinQueue).encode_message(Encoding::Text))
+            .await
+            .unwrap();
+
+        assert_eq!(close_code(&mut client).await, Some(CloseCode::Protocol));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_close_on_hello_timeout() {
+        let mut client = connect().await;
+
+        assert_eq!(close_code(&mut client).await, Some(CloseCode::Policy));
+    }
+}
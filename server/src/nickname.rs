@@ -0,0 +1,226 @@
+use tickoattwo::packet::NicknameError;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// Words rejected as a word of a nickname, regardless of case
+const DEFAULT_BLOCKED_WORDS: &[&str] = &["asshole", "bitch", "cunt", "fuck", "shit"];
+
+// Decides which nicknames are not acceptable
+pub trait Blocklist: Send {
+    fn is_blocked(&self, nickname: &str) -> bool;
+}
+
+// Blocks nicknames containing any of the words as a whole word, so names like
+// "Scunthorpe" pass while "ShitHead" and "fuck99" do not
+pub struct WordBlocklist {
+    words: Vec<String>,
+}
+
+impl Default for WordBlocklist {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCKED_WORDS.iter().map(|word| word.to_string()))
+    }
+}
+
+impl WordBlocklist {
+    pub fn new(words: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            words: words.into_iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl Blocklist for WordBlocklist {
+    fn is_blocked(&self, nickname: &str) -> bool {
+        words(nickname)
+            .iter()
+            .any(|word| self.words.contains(&word.to_lowercase()))
+    }
+}
+
+pub struct NicknamePolicy {
+    // Length limits in characters, after normalisation
+    pub min_length: usize,
+    pub max_length: usize,
+    pub blocklist: Box<dyn Blocklist>,
+}
+
+impl Default for NicknamePolicy {
+    fn default() -> Self {
+        Self::with_blocklist(Box::new(WordBlocklist::default()))
+    }
+}
+
+impl NicknamePolicy {
+    pub fn with_blocklist(blocklist: Box<dyn Blocklist>) -> Self {
+        Self {
+            min_length: 2,
+            max_length: 20,
+            blocklist,
+        }
+    }
+
+    // Normalised nickname if it is acceptable and not taken yet
+    pub fn check(
+        &self,
+        nickname: &str,
+        taken: impl Fn(&str) -> bool,
+    ) -> Result<String, NicknameError> {
+        let nickname = normalize(nickname);
+        let length = nickname.chars().count();
+
+        if length < self.min_length {
+            return Err(NicknameError::TooShort);
+        }
+        if length > self.max_length {
+            return Err(NicknameError::TooLong);
+        }
+        if !is_allowed(&nickname) {
+            return Err(NicknameError::InvalidCharacter);
+        }
+        if self.blocklist.is_blocked(&nickname) {
+            return Err(NicknameError::Blocked);
+        }
+        if taken(&nickname) {
+            return Err(NicknameError::Taken);
+        }
+
+        Ok(nickname)
+    }
+}
+
+// Letters and digits of any script with their combining marks, and a few separators
+fn is_allowed(nickname: &str) -> bool {
+    let mut after_base = false;
+    nickname.chars().all(|c| {
+        let allowed = if is_combining_mark(c) {
+            after_base
+        } else {
+            c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.')
+        };
+        after_base = c.is_alphanumeric() || (after_base && is_combining_mark(c));
+        allowed
+    })
+}
+
+// Split at separators, between letters and digits and where lower case turns upper case
+fn words(nickname: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous: Option<char> = None;
+
+    for c in nickname.chars() {
+        let boundary = match previous {
+            _ if is_combining_mark(c) => false,
+            Some(previous) => {
+                previous.is_numeric() != c.is_numeric()
+                    || (previous.is_lowercase() && c.is_uppercase())
+            }
+            None => false,
+        };
+        if (boundary || !(c.is_alphanumeric() || is_combining_mark(c))) && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() || is_combining_mark(c) {
+            word.push(c);
+        }
+        if !is_combining_mark(c) {
+            previous = Some(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+// Compose characters (NFC), trim and collapse runs of whitespace into a single space
+pub fn normalize(nickname: &str) -> String {
+    let nickname = nickname.nfc().collect::<String>();
+    nickname.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Nicknames are unique regardless of case and how characters are composed
+pub fn same_nickname(a: &str, b: &str) -> bool {
+    let fold = |nickname: &str| nickname.nfc().collect::<String>().to_lowercase();
+    fold(a) == fold(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(nickname: &str) -> Result<String, NicknameError> {
+        NicknamePolicy::default().check(nickname, |_| false)
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(check("  tick \t oat  "), Ok(String::from("tick oat")));
+        assert_eq!(check("Ōat_2"), Ok(String::from("Ōat_2")));
+        assert_eq!(check("チック"), Ok(String::from("チック")));
+        assert_eq!(check("नमस्ते"), Ok(String::from("नमस्ते")));
+
+        // Decomposed characters are composed
+        assert_eq!(check("O\u{304}at"), Ok(String::from("Ōat")));
+    }
+
+    #[test]
+    fn test_rejected() {
+        assert_eq!(check(""), Err(NicknameError::TooShort));
+        assert_eq!(check("  a  "), Err(NicknameError::TooShort));
+        assert_eq!(check(&"a".repeat(10_000)), Err(NicknameError::TooLong));
+        assert_eq!(check("tick\u{0}oat"), Err(NicknameError::InvalidCharacter));
+        assert_eq!(check("<script>"), Err(NicknameError::InvalidCharacter));
+        assert_eq!(
+            check("\u{301}\u{301}oat"),
+            Err(NicknameError::InvalidCharacter)
+        );
+        assert_eq!(check("oat \u{301}"), Err(NicknameError::InvalidCharacter));
+    }
+
+    #[test]
+    fn test_blocked_words() {
+        for nickname in ["ShitHead", "the shit", "shit_head", "fuck99", "SHIT"] {
+            assert_eq!(check(nickname), Err(NicknameError::Blocked), "{}", nickname);
+        }
+
+        // Blocked words inside harmless words
+        for nickname in ["Scunthorpe", "Shitake", "Cocktail", "Bitchin"] {
+            assert!(check(nickname).is_ok(), "{}", nickname);
+        }
+    }
+
+    #[test]
+    fn test_taken() {
+        let policy = NicknamePolicy::default();
+        let taken = |nickname: &str| same_nickname(nickname, "Tick");
+
+        assert_eq!(policy.check(" tick", taken), Err(NicknameError::Taken));
+        assert!(policy.check("tock", taken).is_ok());
+
+        let taken = |nickname: &str| same_nickname(nickname, "O\u{304}at");
+        assert_eq!(policy.check("ōat", taken), Err(NicknameError::Taken));
+    }
+
+    struct NoBlocklist;
+
+    impl Blocklist for NoBlocklist {
+        fn is_blocked(&self, _: &str) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_custom_blocklist() {
+        let policy = NicknamePolicy::with_blocklist(Box::new(NoBlocklist));
+        assert!(policy.check("ShitHead", |_| false).is_ok());
+
+        let policy =
+            NicknamePolicy::with_blocklist(Box::new(WordBlocklist::new([String::from("Admin")])));
+        assert_eq!(
+            policy.check("the admin", |_| false),
+            Err(NicknameError::Blocked)
+        );
+    }
+}
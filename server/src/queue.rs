@@ -0,0 +1,244 @@
+use std::{collections::VecDeque, time::Duration};
+use tokio::time::Instant;
+
+use crate::user::UserId;
+
+pub struct QueueEntry {
+    pub user_id: UserId,
+    pub queued_at: Instant,
+}
+
+// Decides which waiting users play against each other
+pub trait Pairing: Send {
+    // Indices of two entries to pair, entries are ordered by time of joining
+    fn pair(&self, entries: &[&QueueEntry]) -> Option<(usize, usize)>;
+}
+
+// Pairs the two users waiting the longest
+pub struct FifoPairing;
+
+impl Pairing for FifoPairing {
+    fn pair(&self, entries: &[&QueueEntry]) -> Option<(usize, usize)> {
+        (entries.len() >= 2).then_some((0, 1))
+    }
+}
+
+// Waiting room for users looking for an opponent
+pub struct Queue {
+    entries: VecDeque<QueueEntry>,
+    pairing: Box<dyn Pairing>,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self::with_pairing(Box::new(FifoPairing))
+    }
+
+    pub fn with_pairing(pairing: Box<dyn Pairing>) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            pairing,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, user_id: &UserId) -> bool {
+        self.entries.iter().any(|entry| &entry.user_id == user_id)
+    }
+
+    // Returns false if the user is already waiting
+    pub fn join(&mut self, user_id: &UserId) -> bool {
+        if self.contains(user_id) {
+            return false;
+        }
+
+        self.entries.push_back(QueueEntry {
+            user_id: *user_id,
+            queued_at: Instant::now(),
+        });
+        true
+    }
+
+    // Returns how long the user waited, if they were in the queue
+    pub fn leave(&mut self, user_id: &UserId) -> Option<Duration> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| &entry.user_id == user_id)?;
+
+        self.entries
+            .remove(index)
+            .map(|entry| entry.queued_at.elapsed())
+    }
+
+    // Take the next two users to play against each other
+    pub fn next_pair(&mut self) -> Option<(UserId, UserId)> {
+        let entries = self.entries.iter().collect::<Vec<_>>();
+        let (first, second) = self.pairing.pair(&entries)?;
+
+        if first == second || first >= entries.len() || second >= entries.len() {
+            return None;
+        }
+
+        let pair = (entries[first].user_id, entries[second].user_id);
+        self.leave(&pair.0);
+        self.leave(&pair.1);
+
+        Some(pair)
+    }
+
+    // Remove and return users waiting for at least the timeout
+    pub fn take_timed_out(&mut self, timeout: Duration) -> Vec<UserId> {
+        let (timed_out, waiting) = self
+            .entries
+            .drain(..)
+            .partition::<Vec<_>, _>(|entry| entry.queued_at.elapsed() >= timeout);
+
+        self.entries = waiting.into();
+        timed_out.into_iter().map(|entry| entry.user_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{seq::SliceRandom, Rng};
+    use std::collections::HashSet;
+    use tokio::time::advance;
+    use uuid::Uuid;
+
+    fn user(id: u128) -> UserId {
+        UserId(Uuid::from_u128(id))
+    }
+
+    #[test]
+    fn test_fifo_order() {
+        let mut queue = Queue::new();
+
+        assert!(queue.join(&user(1)));
+        assert!(queue.join(&user(2)));
+        assert!(queue.join(&user(3)));
+        assert!(!queue.join(&user(1)));
+
+        assert_eq!(queue.next_pair(), Some((user(1), user(2))));
+        assert_eq!(queue.next_pair(), None);
+        assert_eq!(queue.len(), 1);
+
+        assert!(queue.join(&user(4)));
+        assert_eq!(queue.next_pair(), Some((user(3), user(4))));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_leave() {
+        let mut queue = Queue::new();
+
+        queue.join(&user(1));
+        queue.join(&user(2));
+        queue.join(&user(3));
+
+        assert!(queue.leave(&user(2)).is_some());
+        assert!(queue.leave(&user(2)).is_none());
+        assert!(!queue.contains(&user(2)));
+
+        assert_eq!(queue.next_pair(), Some((user(1), user(3))));
+    }
+
+    // Pairs the two users waiting the shortest time
+    struct NewestPairing;
+
+    impl Pairing for NewestPairing {
+        fn pair(&self, entries: &[&QueueEntry]) -> Option<(usize, usize)> {
+            let len = entries.len();
+            (len >= 2).then_some((len - 1, len - 2))
+        }
+    }
+
+    #[test]
+    fn test_custom_pairing() {
+        let mut queue = Queue::with_pairing(Box::new(NewestPairing));
+
+        queue.join(&user(1));
+        queue.join(&user(2));
+        queue.join(&user(3));
+
+        assert_eq!(queue.next_pair(), Some((user(3), user(2))));
+        assert!(queue.contains(&user(1)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_take_timed_out() {
+        let mut queue = Queue::new();
+
+        queue.join(&user(1));
+        advance(Duration::from_secs(10)).await;
+        queue.join(&user(2));
+        advance(Duration::from_secs(10)).await;
+        queue.join(&user(3));
+
+        assert_eq!(queue.take_timed_out(Duration::from_secs(20)), vec![user(1)]);
+        assert_eq!(queue.take_timed_out(Duration::from_secs(20)), vec![]);
+        assert_eq!(queue.take_timed_out(Duration::from_secs(10)), vec![user(2)]);
+        assert!(queue.contains(&user(3)));
+    }
+
+    // Test random joins and leaves keep the queue consistent with a simple model
+    #[test]
+    fn test_random_joins_and_leaves() {
+        let mut rng = rand::thread_rng();
+        let mut queue = Queue::new();
+        let mut model: Vec<UserId> = Vec::new();
+        let mut paired = HashSet::new();
+
+        for _ in 0..10_000 {
+            let id = user(rng.gen_range(1..200));
+
+            match rng.gen_range(0..3) {
+                0 => {
+                    let joined = queue.join(&id);
+                    assert_eq!(joined, !model.contains(&id));
+                    if joined {
+                        model.push(id);
+                    }
+                }
+                1 => {
+                    let left = queue.leave(&id).is_some();
+                    assert_eq!(left, model.contains(&id));
+                    model.retain(|waiting| waiting != &id);
+                }
+                _ => match queue.next_pair() {
+                    Some((first, second)) => {
+                        assert_eq!((first, second), (model[0], model[1]));
+                        model.drain(..2);
+                        paired.insert(first);
+                        paired.insert(second);
+                    }
+                    None => assert!(model.len() < 2),
+                },
+            }
+
+            assert_eq!(queue.len(), model.len());
+        }
+
+        assert!(!paired.is_empty());
+
+        model.shuffle(&mut rng);
+        for id in model {
+            assert!(queue.leave(&id).is_some());
+        }
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use rand::Rng;
+
+use crate::user::UserId;
+
+// Letters and digits that are hard to confuse when read aloud or typed
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 6;
+
+// Private room for two users, joined by a shared code
+pub struct Room {
+    pub members: Vec<UserId>,
+}
+
+impl Room {
+    pub fn new(creator: &UserId) -> Self {
+        Self {
+            members: vec![*creator],
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+// Generate a room code not taken yet
+pub fn generate_code(taken: impl Fn(&str) -> bool) -> String {
+    let mut rng = rand::thread_rng();
+
+    loop {
+        let code = (0..CODE_LENGTH)
+            .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+            .collect::<String>();
+
+        if !taken(&code) {
+            return code;
+        }
+    }
+}
+
+// Codes are matched regardless of case and surrounding whitespace
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_code() {
+        let code = generate_code(|_| false);
+
+        assert_eq!(code.len(), CODE_LENGTH);
+        assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+        assert_eq!(normalize_code(&code.to_lowercase()), code);
+
+        let other = generate_code(|taken| taken == code);
+        assert_ne!(other, code);
+    }
+}
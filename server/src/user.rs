@@ -1,14 +1,56 @@
+use std::{fmt, net::SocketAddr};
+use tokio::time::Instant;
 use uuid::Uuid;
 
+// Identifies a connected user, independent of its network address
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UserId(pub Uuid);
+
+impl UserId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 pub struct User {
+    // Remote address of the connection, for logging only
+    pub addr: SocketAddr,
+    // Token to resume the session from another connection
+    pub session: Uuid,
+    // Set while the connection is lost and the game is kept for the user
+    pub disconnected_at: Option<Instant>,
     pub game: Option<Uuid>,
+    // Code of the private room the user waits in for a friend
+    pub room: Option<String>,
+    // Game the user is watching
+    pub spectating: Option<Uuid>,
+    // Lobby updates are pushed to the user
+    pub lobby_subscribed: bool,
     pub username: String,
 }
 
 impl User {
-    pub fn new() -> Self {
+    pub fn new(addr: SocketAddr) -> Self {
         Self {
+            addr,
+            session: Uuid::new_v4(),
+            disconnected_at: None,
             game: None,
+            room: None,
+            spectating: None,
+            lobby_subscribed: false,
             username: String::from(""),
         }
     }